@@ -0,0 +1,14 @@
+#version 330
+
+uniform sampler2D image;
+
+in vec2 texpos;
+
+layout(location=0) out vec4 color;
+// nothing to pick, so the click buffer keeps its cleared value
+layout(location=1) out uvec3 click;
+
+void main() {
+  color = texture(image, texpos);
+  click = uvec3(0);
+}
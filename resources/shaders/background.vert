@@ -0,0 +1,13 @@
+#version 330
+layout(location = 0) in vec2 pos;
+
+uniform vec2 size;
+uniform mat4 projection;
+
+out vec2 texpos;
+
+void main() {
+    gl_Position = projection * vec4(pos * size, 0.1, 1.0);
+    // image rows run top to bottom
+    texpos = vec2(pos.x, 1.0 - pos.y);
+}
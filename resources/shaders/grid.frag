@@ -4,15 +4,48 @@ uniform vec2 size;
 uniform sampler2D tilesheet;
 
 in vec2 texpos;
+in vec2 tilepos;
 
 flat in float fragtile;
-uniform float ntiles;
+flat in float fragelevation;
+flat in uvec2 cell;
+// the tileset is packed into a grid of cells, row by row from the top
+uniform ivec2 atlas_cells;
+uniform float atlas_inset;
+uniform float opacity;
 uniform uint renderpass;
+// 0 for squares, 1 for point-up hexes, 2 for flat-top hexes
+uniform int shape;
 
 layout(location=0) out vec4 color;
 layout(location=1) out uvec3 click;
 
+const float apothem = sqrt(3.0) / 2.0;
+
+// p is relative to the cell centre, in units of the hex's circumradius
+bool in_cell(vec2 p) {
+  vec2 a = abs(p);
+  if (shape == 1) {
+    return a.x <= apothem && dot(a, vec2(0.5, apothem)) <= apothem;
+  } else if (shape == 2) {
+    return a.y <= apothem && dot(a, vec2(apothem, 0.5)) <= apothem;
+  }
+  return true;
+}
+
 void main() {
-  color = texture2D(tilesheet, vec2((texpos.x + fragtile) / ntiles, 1 - texpos.y));
-  // click = uvec3(renderpass, 0, 0);
+  // empty hexes are -1
+  if (fragtile < 0.0 || !in_cell(texpos * 2.0 - 1.0)) {
+    discard;
+  }
+  int tile = int(fragtile + 0.5);
+  vec2 atlas_cell = vec2(tile % atlas_cells.x, tile / atlas_cells.x);
+  vec2 within = vec2(tilepos.x, 1.0 - tilepos.y) * (1.0 - 2.0 * atlas_inset) + atlas_inset;
+  color = texture(tilesheet, (atlas_cell + within) / vec2(atlas_cells));
+  color.a *= opacity;
+  // lighten high ground and darken low ground, more so towards the hex's rim
+  float rim = smoothstep(0.6, 1.0, length(texpos * 2.0 - 1.0));
+  float shade = min(abs(fragelevation) * 0.12, 0.6) * (0.6 + 0.4 * rim);
+  color.rgb = mix(color.rgb, vec3(step(0.0, fragelevation)), shade);
+  click = uvec3(renderpass, cell);
 }
\ No newline at end of file
@@ -2,17 +2,63 @@
 layout(location = 0) in vec2 pos;
 layout(location = 1) in vec2 offset;
 layout(location = 2) in float tile;
+layout(location = 3) in float elevation;
+layout(location = 4) in float orientation;
 
 uniform vec2 size;
 uniform mat4 projection;
+uniform int shape;
+// milliseconds, for picking frames of animated tiles
+uniform float time;
+uniform int tile_count;
+// animated tiles as (tile, first frame, frame count), frames being numbered
+// from the atlas cell after the last tile
+uniform int animation_count;
+uniform ivec3 animations[32];
+// when each frame ends, counted from the start of its animation
+uniform float frame_ends[128];
+
+// where the chunk being drawn starts, its instances going row by row
+uniform ivec2 chunk_origin;
+uniform int chunk_width;
 
 out vec2 texpos;
+out vec2 tilepos;
 flat out float fragtile;
-flat out int iid;
+flat out float fragelevation;
+flat out uvec2 cell;
+
+// the atlas cell with the current frame of the tile
+float frame(float tile) {
+    int t = int(tile + 0.5);
+    for (int i = 0; i < animation_count; i++) {
+        if (animations[i].x != t) {
+            continue;
+        }
+        int first = animations[i].y;
+        int count = animations[i].z;
+        float now = mod(time, frame_ends[first + count - 1]);
+        int n = 0;
+        while (n < count - 1 && now >= frame_ends[first + n]) {
+            n++;
+        }
+        return float(tile_count + first + n);
+    }
+    return tile;
+}
 
 void main() {
     gl_Position = projection * vec4(offset + pos * size, 0.5, 1.0);
     texpos = pos;
-    fragtile = tile;
-    iid = gl_InstanceID;
+    // the tile is mirrored, then turned clockwise, so undo that in reverse
+    int code = int(orientation + 0.5);
+    float angle = float(code & 15) * (shape == 0 ? radians(90.0) : radians(60.0));
+    vec2 p = mat2(cos(angle), sin(angle), -sin(angle), cos(angle)) * (pos - 0.5);
+    if (code >= 16) {
+        p.x = -p.x;
+    }
+    tilepos = p + 0.5;
+    fragtile = tile < 0.0 ? tile : frame(tile);
+    fragelevation = elevation;
+    cell = uvec2(chunk_origin + ivec2(gl_InstanceID % chunk_width, gl_InstanceID / chunk_width));
 }
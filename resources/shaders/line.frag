@@ -0,0 +1,9 @@
+#version 330
+
+flat in vec4 fragcolour;
+
+layout(location=0) out vec4 color;
+
+void main() {
+  color = fragcolour;
+}
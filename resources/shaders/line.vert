@@ -0,0 +1,17 @@
+#version 330
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec4 ends;
+layout(location = 2) in vec4 colour;
+layout(location = 3) in float width;
+
+uniform mat4 projection;
+
+flat out vec4 fragcolour;
+
+void main() {
+    vec2 along = ends.zw - ends.xy;
+    vec2 across = length(along) > 0.0 ? normalize(vec2(-along.y, along.x)) : vec2(0.0, 1.0);
+    vec2 p = ends.xy + along * pos.x + across * width * (pos.y - 0.5);
+    gl_Position = projection * vec4(p, 0.9, 1.0);
+    fragcolour = colour;
+}
@@ -0,0 +1,29 @@
+#version 330
+
+// 0 for squares, 1 for point-up hexes, 2 for flat-top hexes
+uniform int shape;
+
+in vec2 texpos;
+flat in vec4 fragtint;
+
+layout(location=0) out vec4 color;
+
+const float apothem = sqrt(3.0) / 2.0;
+
+// p is relative to the cell centre, in units of the hex's circumradius
+bool in_cell(vec2 p) {
+  vec2 a = abs(p);
+  if (shape == 1) {
+    return a.x <= apothem && dot(a, vec2(0.5, apothem)) <= apothem;
+  } else if (shape == 2) {
+    return a.y <= apothem && dot(a, vec2(apothem, 0.5)) <= apothem;
+  }
+  return true;
+}
+
+void main() {
+  if (!in_cell(texpos * 2.0 - 1.0)) {
+    discard;
+  }
+  color = fragtint;
+}
\ No newline at end of file
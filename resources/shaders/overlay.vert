@@ -0,0 +1,16 @@
+#version 330
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec2 offset;
+layout(location = 2) in vec4 tint;
+
+uniform vec2 size;
+uniform mat4 projection;
+
+out vec2 texpos;
+flat out vec4 fragtint;
+
+void main() {
+    gl_Position = projection * vec4(offset + pos * size, 0.75, 1.0);
+    texpos = pos;
+    fragtint = tint;
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+#version 330
+
+uniform sampler2D atlas;
+
+in vec2 texpos;
+flat in vec4 fragcolour;
+
+layout(location=0) out vec4 color;
+
+void main() {
+  color = vec4(fragcolour.rgb, fragcolour.a * texture(atlas, texpos).a);
+}
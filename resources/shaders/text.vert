@@ -0,0 +1,17 @@
+#version 330
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec4 rect;
+layout(location = 2) in vec4 uv;
+layout(location = 3) in vec4 colour;
+
+uniform mat4 projection;
+
+out vec2 texpos;
+flat out vec4 fragcolour;
+
+void main() {
+    gl_Position = projection * vec4(rect.xy + pos * rect.zw, 0.9, 1.0);
+    // atlas rows run top to bottom
+    texpos = uv.xy + vec2(pos.x, 1.0 - pos.y) * uv.zw;
+    fragcolour = colour;
+}
@@ -2,16 +2,45 @@
 
 uniform sampler2D token;
 uniform uint renderpass;
+// 0 for none, 1 for a plate behind the art, 2 for clipping it, as Mask
+uniform int mask;
+uniform sampler2D mask_texture;
+// how much larger the quad is than the art and the mask
+uniform vec2 art_scale;
+uniform vec2 mask_scale;
 
 in vec2 texpos;
-flat in uint frag_token_name;
+flat in uint frag_instance;
 
 layout(location=0) out vec4 color;
 layout(location=1) out uvec3 click;
 
+const vec4 plate = vec4(0.85, 0.83, 0.78, 1.0);
+
+// transparent outside the texture rather than clamped to its edge
+vec4 sample_scaled(sampler2D sampler, vec2 scale) {
+    vec2 p = (texpos - 0.5) * scale + 0.5;
+    if (any(lessThan(p, vec2(0.0))) || any(greaterThan(p, vec2(1.0)))) {
+        return vec4(0.0);
+    }
+    return texture(sampler, p);
+}
+
 void main() {
-    color = texture2D(token, texpos);
-    if (texture2D(token, texpos).a > 0.0) {
-        click = uvec3(renderpass, (frag_token_name >> 8) & uint(0xff), frag_token_name & uint(0xff));
+    color = sample_scaled(token, art_scale);
+    if (mask != 0) {
+        float cover = sample_scaled(mask_texture, mask_scale).a;
+        if (mask == 2) {
+            color.a *= cover;
+        } else {
+            float alpha = color.a + cover * (1.0 - color.a);
+            vec3 rgb = color.rgb * color.a + plate.rgb * cover * (1.0 - color.a);
+            color = alpha > 0.0 ? vec4(rgb / alpha, alpha) : vec4(0.0);
+        }
+    }
+    // clicks go through the transparent parts to whatever is beneath
+    if (color.a <= 0.0) {
+        discard;
     }
+    click = uvec3(renderpass, frag_instance, 0u);
 }
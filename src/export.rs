@@ -0,0 +1,172 @@
+use crate::fgl::framebuffer::{self, Attachment, FrameBuffer, RenderBuffer};
+use crate::fgl::texture::{self, Texture2D};
+use crate::fgl::Program;
+use crate::hex::grid::HexGrid;
+use crate::hex::outline::{GridOutline, OutlineStyle};
+use crate::hex::token::TokenManager;
+use cgmath::Vector2;
+use image::RgbaImage;
+use std::path::Path;
+
+/// Largest piece of the image rendered at once, whatever the driver allows
+const MAX_TILE: u32 = 4096;
+
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Pixels per world unit, so 1 exports a background image at its own size
+    pub scale: f32,
+    /// Grid lines and labels drawn over the tiles, none if `None`
+    pub grid_lines: Option<OutlineStyle>,
+    /// Leaves out layers marked as GM-only
+    pub hide_gm_only: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            grid_lines: None,
+            hide_gm_only: true,
+        }
+    }
+}
+
+/// Renders the whole map and its tokens offscreen and saves it as a PNG
+pub fn export_png(
+    path: impl AsRef<Path>,
+    grid: &HexGrid,
+    tokens: &TokenManager,
+    program: &Program,
+    options: &ExportOptions,
+) -> Result<(), ExportError> {
+    let image = render(grid, tokens, program, options)?;
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(ExportError::Image)
+}
+
+/// Renders the whole map and its tokens onto a white image
+///
+/// Images bigger than the driver can render to in one go are put together
+/// from several renders. The caller's viewport is restored afterwards.
+pub fn render(
+    grid: &HexGrid,
+    tokens: &TokenManager,
+    program: &Program,
+    options: &ExportOptions,
+) -> Result<RgbaImage, ExportError> {
+    let (min, max) = grid.bounds().ok_or(ExportError::Empty)?;
+    let size = (max - min) * options.scale;
+    let (width, height) = (size.x.ceil() as u32, size.y.ceil() as u32);
+    if width == 0 || height == 0 {
+        return Err(ExportError::Empty);
+    }
+    if (width as u64 * height as u64 * 4) > isize::MAX as u64 {
+        return Err(ExportError::TooLarge(width, height));
+    }
+
+    let mut outline = match &options.grid_lines {
+        Some(style) => Some(GridOutline::new(grid, *style).map_err(ExportError::Gl)?),
+        None => None,
+    };
+
+    let tile = texture::max_texture_size()
+        .min(framebuffer::max_renderbuffer_size())
+        .min(MAX_TILE);
+    let framebuffer = FrameBuffer::new();
+    let colour = Texture2D::with_dimensions(tile as i32, tile as i32, texture::Format::Rgba);
+    framebuffer.attach_texture2d(&colour, Attachment::Color(0));
+    let depth = RenderBuffer::new();
+    depth.alloc(tile, tile, framebuffer::Format::DepthStencil, 0);
+    framebuffer.attach_renderbuffer(&depth, Attachment::DepthStencil);
+    framebuffer.set_draw_buffers(&[Some(0)]);
+    if let Some(status) = framebuffer.status() {
+        return Err(ExportError::Gl(format!(
+            "framebuffer incomplete: {:?}",
+            status
+        )));
+    }
+
+    let mut viewport = [0i32; 4];
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        gl::Viewport(0, 0, tile as i32, tile as i32);
+    }
+
+    let mut image = RgbaImage::new(width, height);
+    for y in (0..height).step_by(tile as usize) {
+        for x in (0..width).step_by(tile as usize) {
+            // every render covers a whole tile so pixels stay square, only
+            // the part inside the image is read back
+            let corner = min + Vector2::new(x as f32, y as f32) / options.scale;
+            let extent = tile as f32 / options.scale;
+            let projection = cgmath::ortho(
+                corner.x,
+                corner.x + extent,
+                corner.y,
+                corner.y + extent,
+                -1.0,
+                100.0,
+            );
+
+            framebuffer.bind();
+            unsafe {
+                gl::ClearColor(1.0, 1.0, 1.0, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+                if options.hide_gm_only {
+                    grid.draw_for_players(program, projection);
+                } else {
+                    grid.draw(program, projection);
+                }
+            }
+            framebuffer.bind();
+            if let Some(outline) = &mut outline {
+                outline.draw(projection);
+            }
+            tokens.draw(projection);
+
+            let (w, h) = ((width - x).min(tile), (height - y).min(tile));
+            let pixels = framebuffer.read_pixels(0, 0, w, h);
+            for (row, pixels) in pixels.chunks(w as usize * 4).enumerate() {
+                let target = height - 1 - (y + row as u32);
+                for (column, pixel) in pixels.chunks(4).enumerate() {
+                    // blending over the opaque clear colour still lowers the
+                    // alpha written, though the colours are already final
+                    let pixel = image::Rgba([pixel[0], pixel[1], pixel[2], 255]);
+                    image.put_pixel(x + column as u32, target, pixel);
+                }
+            }
+        }
+    }
+
+    framebuffer.unbind();
+    unsafe {
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+    Ok(image)
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// The grid has no cells and there is no background
+    Empty,
+    TooLarge(u32, u32),
+    Image(image::ImageError),
+    /// Setting up the framebuffer or shaders failed
+    Gl(String),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Empty => write!(f, "there is nothing to export"),
+            ExportError::TooLarge(width, height) => {
+                write!(f, "a {}x{} image is too large to export", width, height)
+            }
+            ExportError::Image(e) => write!(f, "couldn't write the image: {}", e),
+            ExportError::Gl(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
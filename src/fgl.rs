@@ -66,13 +66,13 @@ impl VertexBuffer {
     }
 
     pub fn replace_sub_data<T>(&self, offset: usize, data: &[T]) {
-        assert!((offset + data.len()) * std::mem::size_of::<T>() < self.len);
+        assert!((offset + data.len()) * std::mem::size_of::<T>() <= self.len);
         self.bind();
         unsafe {
             gl::BufferSubData(
                 gl::ARRAY_BUFFER,
                 offset as isize * std::mem::size_of::<T>() as isize,
-                data.len() as isize,
+                std::mem::size_of_val(data) as isize,
                 &data[0] as *const _ as *const c_void,
             );
         }
@@ -146,14 +146,24 @@ impl VertexAttribObject {
             if let Some(divisor) = ptr.divisor {
                 gl::VertexAttribDivisor(ptr.id, divisor)
             }
-            gl::VertexAttribPointer(
-                ptr.id,
-                ptr.ncomponents,
-                T::to_enum(),
-                if ptr.normalise { gl::TRUE } else { gl::FALSE },
-                ptr.stride,
-                ptr.offset as *mut c_void,
-            );
+            if ptr.integer {
+                gl::VertexAttribIPointer(
+                    ptr.id,
+                    ptr.ncomponents,
+                    T::to_enum(),
+                    ptr.stride,
+                    ptr.offset as *mut c_void,
+                );
+            } else {
+                gl::VertexAttribPointer(
+                    ptr.id,
+                    ptr.ncomponents,
+                    T::to_enum(),
+                    if ptr.normalise { gl::TRUE } else { gl::FALSE },
+                    ptr.stride,
+                    ptr.offset as *mut c_void,
+                );
+            }
         }
     }
 }
@@ -251,6 +261,7 @@ pub struct VertexAttribArray<T: GlType> {
     divisor: Option<u32>,
     ncomponents: i32,
     normalise: bool,
+    integer: bool,
     stride: i32,
     offset: i32,
     _pd: std::marker::PhantomData<T>,
@@ -262,6 +273,7 @@ impl<T: GlType> VertexAttribArray<T> {
             id,
             divisor: None,
             normalise: false,
+            integer: false,
             stride: 0,
             offset: 0,
             ncomponents: 1,
@@ -272,6 +284,11 @@ impl<T: GlType> VertexAttribArray<T> {
         self.normalise = true;
         self
     }
+    /// Passes values on as integers, for `int` and `uint` shader inputs
+    pub fn integer(mut self) -> Self {
+        self.integer = true;
+        self
+    }
     pub fn with_divisor(mut self, divisor: u32) -> Self {
         self.divisor = Some(divisor);
         self
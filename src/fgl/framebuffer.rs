@@ -132,6 +132,45 @@ impl FrameBuffer {
         unsafe { C::clear(buffer, color.as_ptr()) }
     }
 
+    /// RGBA bytes of a rectangle of colour attachment 0, bottom row first
+    pub fn read_pixels(&self, x: i32, y: i32, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        self.bind();
+        unsafe {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                x,
+                y,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+        pixels
+    }
+
+    /// The pixel at `x`, `y` of an unsigned integer colour attachment
+    pub fn read_integer_pixel(&self, attachment: u32, x: i32, y: i32) -> [u32; 4] {
+        let mut pixel = [0u32; 4];
+        self.bind();
+        unsafe {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment);
+            gl::ReadPixels(
+                x,
+                y,
+                1,
+                1,
+                gl::RGBA_INTEGER,
+                gl::UNSIGNED_INT,
+                pixel.as_mut_ptr() as *mut _,
+            );
+        }
+        pixel
+    }
+
     pub fn bind(&self) {
         unsafe {
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
@@ -184,6 +223,15 @@ impl Format {
     }
 }
 
+/// Largest width or height the driver accepts for a renderbuffer
+pub fn max_renderbuffer_size() -> u32 {
+    let mut size = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_RENDERBUFFER_SIZE, &mut size as *mut _);
+    }
+    size as u32
+}
+
 pub struct RenderBuffer {
     pub(in crate::fgl) id: GLuint,
 }
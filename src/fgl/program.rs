@@ -173,6 +173,18 @@ impl Program {
             gl::Uniform1fv(uniform_id, 1, &uniform);
         }
     }
+    pub fn uniform_ivec3_array(&self, name: &str, uniform: &[Vector3<i32>]) {
+        unsafe {
+            let uniform_id = self.get_uniform_location(name);
+            gl::Uniform3iv(uniform_id, uniform.len() as i32, uniform.as_ptr() as *const i32);
+        }
+    }
+    pub fn uniform_f32_array(&self, name: &str, uniform: &[f32]) {
+        unsafe {
+            let uniform_id = self.get_uniform_location(name);
+            gl::Uniform1fv(uniform_id, uniform.len() as i32, uniform.as_ptr());
+        }
+    }
 }
 
 fn shader_info_log(shader: GLuint) -> String {
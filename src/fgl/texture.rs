@@ -41,6 +41,9 @@ pub enum Format {
     Rgba,
     Bgr,
     Bgra,
+    /// Four unsigned integer channels, for render targets read back with
+    /// `FrameBuffer::read_integer_pixel`
+    Rgba32Ui,
 }
 
 impl Format {
@@ -50,6 +53,7 @@ impl Format {
             Self::Rgba => gl::RGBA8,
             Self::Bgr => gl::RGB8,
             Self::Bgra => gl::RGBA8,
+            Self::Rgba32Ui => gl::RGBA32UI,
         }) as i32
     }
     fn into_format(self) -> u32 {
@@ -58,6 +62,13 @@ impl Format {
             Self::Rgba => gl::RGBA,
             Self::Bgr => gl::BGR,
             Self::Bgra => gl::BGRA,
+            Self::Rgba32Ui => gl::RGBA_INTEGER,
+        }
+    }
+    fn pixel_type(self) -> u32 {
+        match self {
+            Self::Rgba32Ui => gl::UNSIGNED_INT,
+            _ => gl::UNSIGNED_BYTE,
         }
     }
 }
@@ -96,6 +107,15 @@ fn format(image: &DynamicImage) -> Format {
     }
 }
 
+/// Largest width or height the driver accepts for a 2D texture
+pub fn max_texture_size() -> u32 {
+    let mut size = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut size as *mut _);
+    }
+    size as u32
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum Filter {
     Nearest,
@@ -195,10 +215,16 @@ impl Texture2D {
                 height as i32,
                 0,
                 format.into_format(),
-                gl::UNSIGNED_BYTE,
+                format.pixel_type(),
                 std::ptr::null(),
             );
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+            if let Format::Rgba32Ui = format {
+                // integer textures can't be filtered, so they have no mipmaps
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            } else {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
         }
         Texture2D { id }
     }
@@ -1,7 +1,23 @@
+pub mod atlas;
+pub mod background;
+pub mod calibration;
+pub mod chunk;
+pub mod coord;
 pub mod grid;
+pub mod layer;
+pub mod layout;
+pub mod outline;
+pub mod overlay;
+pub mod path;
+pub mod pick;
+pub mod ruler;
+pub mod sight;
+pub mod template;
+pub mod tile;
 pub mod token;
 
-use cgmath::Vector2;
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector4};
+use coord::FractionalHex;
 
 fn long_radius(short_radius: f32) -> f32 {
     short_radius * 2f32 / 3f32.sqrt()
@@ -10,39 +26,134 @@ fn short_radius(long_radius: f32) -> f32 {
     long_radius * 3f32.sqrt() / 2f32
 }
 
-fn grid_to_world(coords: Vector2<u32>, tile_size: f32) -> Vector2<f32> {
-    let stepx = short_radius(tile_size);
-    let stepy = tile_size * 3.0 / 4.0;
+/// World position of the centre of the hex at `coords`
+pub fn grid_to_world(coords: Vector2<u32>, tile_size: f32, point_up: bool) -> Vector2<f32> {
+    tile_origin(coords, tile_size, point_up) + Vector2::new(tile_size / 2.0, tile_size / 2.0)
+}
 
-    let x = coords.x as f32 * stepx - if coords.y % 2 == 0 { stepx / 2.0 } else { 0.0 };
-    let y = coords.y as f32 * stepy;
-    Vector2::new(x, y)
+/// Hex containing the world position, the inverse of `grid_to_world`
+///
+/// Returns `None` for positions left of or below the grid, which have no
+/// offset coordinates.
+pub fn world_to_grid(world: Vector2<f32>, tile_size: f32, point_up: bool) -> Option<Vector2<u32>> {
+    let radius = tile_size / 2.0;
+    // relative to the centre of hex (0, 0), this is the usual odd-row (or
+    // odd-column for flat-top grids) layout
+    let p = world - grid_to_world(Vector2::new(0, 0), tile_size, point_up);
+    let hex = if point_up {
+        FractionalHex::new(
+            (3f32.sqrt() / 3.0 * p.x - p.y / 3.0) / radius,
+            (2.0 / 3.0 * p.y) / radius,
+        )
+    } else {
+        FractionalHex::new(
+            (2.0 / 3.0 * p.x) / radius,
+            (-p.x / 3.0 + 3f32.sqrt() / 3.0 * p.y) / radius,
+        )
+    };
+    hex.round().to_offset(point_up)
 }
 
-fn grid_coords(height: u32, width: u32, tile_size: f32) -> Vec<f32> {
-    let stepx = short_radius(tile_size);
-    let stepy = tile_size * 3.0 / 4.0;
+/// Unprojects a window position (in pixels, y pointing down) into world space
+///
+/// `view` is the full projection * scale * scroll matrix used for drawing.
+pub fn screen_to_world(
+    screen: Vector2<f32>,
+    viewport: Vector2<f32>,
+    view: Matrix4<f32>,
+) -> Option<Vector2<f32>> {
+    let ndc = Vector4::new(
+        2.0 * screen.x / viewport.x - 1.0,
+        1.0 - 2.0 * screen.y / viewport.y,
+        0.0,
+        1.0,
+    );
+    let world = view.invert()? * ndc;
+    Some(Vector2::new(world.x / world.w, world.y / world.w))
+}
 
-    let mut grid = Vec::new();
-    for row in 0..height {
-        for i in 0..width {
-            grid.push(i as f32 * stepx - if row % 2 == 0 { stepx / 2.0 } else { 0.0 });
-            grid.push(row as f32 * stepy);
-        }
+/// Bottom left corner of the quad the tile at `coords` is drawn into
+///
+/// Point-up grids shift even rows half a hex to the left, flat-top grids
+/// shift even columns half a hex down.
+fn tile_origin(coords: Vector2<u32>, tile_size: f32, point_up: bool) -> Vector2<f32> {
+    if point_up {
+        let stepx = short_radius(tile_size);
+        let stepy = tile_size * 3.0 / 4.0;
+
+        let x = coords.x as f32 * stepx - if coords.y.is_multiple_of(2) { stepx / 2.0 } else { 0.0 };
+        let y = coords.y as f32 * stepy;
+        Vector2::new(x, y)
+    } else {
+        let stepx = tile_size * 3.0 / 4.0;
+        let stepy = short_radius(tile_size);
+
+        let x = coords.x as f32 * stepx;
+        let y = coords.y as f32 * stepy - if coords.x.is_multiple_of(2) { stepy / 2.0 } else { 0.0 };
+        Vector2::new(x, y)
     }
-    grid
 }
 
-fn corner_offset(tile_size: f32, corner: u8) -> Vector2<f32> {
+/// Offset of a hex corner from the hex centre
+///
+/// Corners are numbered clockwise, starting at the top corner of point-up
+/// hexes and the upper right corner of flat-top hexes.
+fn corner_offset(tile_size: f32, corner: u8, point_up: bool) -> Vector2<f32> {
     assert!(corner < 6);
-    match corner {
-        0 => Vector2::new(short_radius(tile_size), 2.0 * tile_size),
-        1 => Vector2::new(2.0 * short_radius(tile_size), 3.0 / 4.0 * tile_size),
-        2 => Vector2::new(2.0 * short_radius(tile_size), 1.0 / 4.0 * tile_size),
-        3 => Vector2::new(short_radius(tile_size), 0.0),
-        4 => Vector2::new(0.0, 1.0 / 4.0 * tile_size),
-        5 => Vector2::new(0.0, 3.0 / 4.0 * tile_size),
-        _ => unreachable!()
+    let start = if point_up { 90.0 } else { 60.0 };
+    let angle = (start - 60.0 * corner as f32).to_radians();
+    Vector2::new(angle.cos(), angle.sin()) * tile_size / 2.0
+}
+
+/// Directions of the two neighbours that share `corner` with a hex
+fn corner_directions(corner: u8, point_up: bool) -> [usize; 2] {
+    assert!(corner < 6);
+    let corner = corner as usize;
+    if point_up {
+        [(corner + 4) % 6, (corner + 5) % 6]
+    } else {
+        [(corner + 5) % 6, corner]
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    const TILE_SIZE: f32 = 64.0;
+
+    #[test]
+    fn world_to_grid_inverts_grid_to_world() {
+        for &point_up in &[true, false] {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let coords = Vector2::new(x, y);
+                    let centre = grid_to_world(coords, TILE_SIZE, point_up);
+                    assert_eq!(world_to_grid(centre, TILE_SIZE, point_up), Some(coords));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_grid_finds_the_hex_around_a_position() {
+        let apothem = short_radius(TILE_SIZE / 2.0);
+        for &point_up in &[true, false] {
+            let coords = Vector2::new(3, 4);
+            let centre = grid_to_world(coords, TILE_SIZE, point_up);
+            for n in 0..12 {
+                let angle = (n as f32 * 30.0).to_radians();
+                let world = centre + Vector2::new(angle.cos(), angle.sin()) * apothem * 0.95;
+                assert_eq!(world_to_grid(world, TILE_SIZE, point_up), Some(coords));
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_grid_is_none_outside_the_grid() {
+        for &point_up in &[true, false] {
+            let world = Vector2::new(-TILE_SIZE * 2.0, -TILE_SIZE * 2.0);
+            assert_eq!(world_to_grid(world, TILE_SIZE, point_up), None);
+        }
+    }
 }
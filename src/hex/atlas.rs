@@ -0,0 +1,116 @@
+use super::tile::Tile;
+use crate::fgl::{self, Program};
+use cgmath::Vector3;
+use image::{imageops, DynamicImage, GenericImageView, RgbaImage};
+
+/// Transparent border around each tile, so filtering doesn't bleed between them
+const PADDING: u32 = 4;
+/// Sizes of the animation uniform arrays in `grid.vert`
+const MAX_ANIMATED: usize = 32;
+const MAX_FRAMES: usize = 128;
+
+/// A tileset packed into a grid of equal cells on one texture
+///
+/// Tiles are scaled to fit their cell, keeping their aspect ratio, and
+/// centred in it. Cells shrink if the whole set wouldn't fit in the largest
+/// texture the driver supports.
+///
+/// Each tile's first frame is in the cell with the tile's index, the frames
+/// of animated tiles follow after the last tile. Tiles past the first
+/// `MAX_ANIMATED`, or whose frames no longer fit in `MAX_FRAMES`, stay on
+/// their first frame.
+pub struct TileAtlas {
+    texture: fgl::texture::Texture2D,
+    columns: u32,
+    rows: u32,
+    /// Fraction of a cell taken up by padding on each side
+    inset: f32,
+    tiles: u32,
+    /// Tile index, first frame and frame count, frames counted from the cell after the last tile
+    animations: Vec<Vector3<i32>>,
+    /// When each frame ends in milliseconds, counted from the start of its animation
+    frame_ends: Vec<f32>,
+}
+
+impl TileAtlas {
+    pub fn new(tiles: &[Tile]) -> Self {
+        let mut images: Vec<&DynamicImage> = tiles.iter().map(Tile::first_frame).collect();
+        let mut animations = Vec::new();
+        let mut frame_ends = Vec::new();
+        for (n, tile) in tiles.iter().enumerate() {
+            let durations = tile.durations();
+            if durations.is_empty()
+                || animations.len() == MAX_ANIMATED
+                || frame_ends.len() + durations.len() > MAX_FRAMES
+            {
+                continue;
+            }
+            let first = frame_ends.len() as i32;
+            animations.push(Vector3::new(n as i32, first, durations.len() as i32));
+            let mut end = 0.0;
+            for duration in durations {
+                end += duration.as_millis().max(1) as f32;
+                frame_ends.push(end);
+            }
+            images.extend(tile.frames());
+        }
+
+        let count = images.len().max(1) as u32;
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        let largest = images
+            .iter()
+            .map(|image| u32::max(image.width(), image.height()))
+            .max()
+            .unwrap_or(1);
+        let limit = fgl::texture::max_texture_size() / columns.max(rows);
+        let stride = (largest + 2 * PADDING).min(limit);
+        let cell = stride.saturating_sub(2 * PADDING).max(1);
+
+        let mut atlas = RgbaImage::new(columns * stride, rows * stride);
+        for (n, image) in images.into_iter().enumerate() {
+            let n = n as u32;
+            let image = if u32::max(image.width(), image.height()) != cell {
+                image.resize(cell, cell, imageops::FilterType::Triangle)
+            } else {
+                image.clone()
+            };
+            let x = (n % columns) * stride + PADDING + (cell - image.width()) / 2;
+            let y = (n / columns) * stride + PADDING + (cell - image.height()) / 2;
+            imageops::replace(&mut atlas, &image.into_rgba8(), x, y);
+        }
+
+        Self {
+            texture: fgl::texture::Texture2D::from_image(DynamicImage::ImageRgba8(atlas)),
+            columns,
+            rows,
+            inset: PADDING as f32 / stride as f32,
+            tiles: tiles.len() as u32,
+            animations,
+            frame_ends,
+        }
+    }
+
+    pub fn animated(&self) -> bool {
+        !self.animations.is_empty()
+    }
+
+    /// Binds the atlas to texture unit 0 and sets the uniforms `grid.frag` looks tiles up with
+    ///
+    /// The animation uniforms go to `grid.vert`, which picks the frame.
+    pub fn bind(&self, program: &Program) {
+        self.texture.bind(0);
+        program.uniform_i32("tilesheet", 0);
+        program.uniform_ivec2(
+            "atlas_cells",
+            cgmath::Vector2::new(self.columns as i32, self.rows as i32),
+        );
+        program.uniform_f32("atlas_inset", self.inset);
+        program.uniform_i32("tile_count", self.tiles as i32);
+        program.uniform_i32("animation_count", self.animations.len() as i32);
+        if self.animated() {
+            program.uniform_ivec3_array("animations", &self.animations);
+            program.uniform_f32_array("frame_ends", &self.frame_ends);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::fgl::{self, texture::Texture2D, Bindable, Program, ProgramBuilder, Shader};
+use cgmath::Vector2;
+use image::{DynamicImage, GenericImageView};
+
+const VERT: &str = include_str!("../../resources/shaders/background.vert");
+const FRAG: &str = include_str!("../../resources/shaders/background.frag");
+
+/// A pre-drawn map image under the grid
+///
+/// The image's bottom left corner sits at the world origin and each pixel is
+/// one world unit, so a calibrated layout lines the grid up with the one drawn
+/// on it.
+pub struct Background {
+    texture: Texture2D,
+    size: Vector2<u32>,
+    vbo: fgl::VertexBuffer,
+    vao: fgl::VertexAttribObject,
+    program: Program,
+}
+
+impl Background {
+    pub fn new(image: DynamicImage) -> Result<Self, String> {
+        let vao = fgl::VertexAttribObject::new();
+        let mut vbo = fgl::VertexBuffer::new();
+        vbo.alloc_with(
+            &fgl::consts::QUAD,
+            fgl::AccessFrequency::Static,
+            fgl::AccessType::Draw,
+        );
+        vao.vertex_attribute_array(
+            &vbo,
+            fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
+        );
+
+        let program = ProgramBuilder::default()
+            .attach_shader(Shader::from_source(fgl::ShaderType::Fragment, FRAG)?)
+            .attach_shader(Shader::from_source(fgl::ShaderType::Vertex, VERT)?)
+            .link()?;
+
+        Ok(Self {
+            size: image.dimensions().into(),
+            texture: Texture2D::from_image(image),
+            vbo,
+            vao,
+            program,
+        })
+    }
+
+    /// Width and height in world units
+    pub fn size(&self) -> Vector2<f32> {
+        self.size.map(|x| x as f32)
+    }
+
+    pub fn draw(&self, projection: cgmath::Matrix4<f32>) {
+        self.vao.bind();
+        self.program.bind();
+        self.program.uniform_mat4("projection", &projection);
+        self.program.uniform_vec2("size", self.size());
+        self.texture.bind(0);
+        self.program.uniform_i32("image", 0);
+        unsafe {
+            gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, 1);
+        }
+    }
+}
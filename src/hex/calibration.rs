@@ -0,0 +1,171 @@
+use super::layout::{Layout, Shape};
+use cgmath::{InnerSpace, Vector2};
+
+/// Works out a layout from cell centres marked on a background image
+///
+/// The marks should be the centres of a straight run of neighbouring cells,
+/// in order: along a row for point-up hexes, up a column for flat-top ones,
+/// either way for squares. Which of the two a hex grid is follows from the
+/// direction of the run. The more cells the run spans, the less a slightly
+/// misplaced mark matters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    square: bool,
+    marks: Vec<Vector2<f32>>,
+}
+
+impl Calibration {
+    pub fn hex() -> Self {
+        Self {
+            square: false,
+            marks: Vec::new(),
+        }
+    }
+
+    pub fn square() -> Self {
+        Self {
+            square: true,
+            marks: Vec::new(),
+        }
+    }
+
+    pub fn mark(&mut self, centre: Vector2<f32>) {
+        self.marks.push(centre);
+    }
+
+    /// Removes the last mark
+    pub fn undo(&mut self) {
+        self.marks.pop();
+    }
+
+    pub fn marks(&self) -> &[Vector2<f32>] {
+        &self.marks
+    }
+
+    /// The layout matching the marks, `None` until there are two distinct ones
+    ///
+    /// The origin is moved back by whole cells (or pairs of staggered rows or
+    /// columns) so the grid starts at or before the image's bottom left corner.
+    pub fn layout(&self) -> Option<Layout> {
+        if self.marks.len() < 2 {
+            return None;
+        }
+        let (first, last) = (self.marks[0], self.marks[self.marks.len() - 1]);
+        let along = last - first;
+        let spacing = along.magnitude() / (self.marks.len() - 1) as f32;
+        if spacing < 1.0 {
+            return None;
+        }
+
+        let layout = if self.square {
+            Layout::new(Shape::Square, spacing)
+        } else {
+            // neighbours of point-up hexes lie at multiples of 60° from the
+            // horizontal, those of flat-top hexes halfway between
+            let angle = along.y.atan2(along.x).to_degrees().rem_euclid(60.0);
+            let point_up = !(15.0..45.0).contains(&angle);
+            Layout::new(Shape::Hex { point_up }, spacing * 2.0 / 3f32.sqrt())
+        };
+        let period = period(layout);
+        let origin = first - layout.cell_to_world(Vector2::new(0, 0));
+        let origin = Vector2::new(
+            origin.x - (origin.x / period.x).ceil() * period.x,
+            origin.y - (origin.y / period.y).ceil() * period.y,
+        );
+        Some(layout.with_origin(origin))
+    }
+}
+
+/// Width and height of a grid with `layout` that covers an image of `size`
+pub fn dimensions_to_cover(layout: Layout, size: Vector2<f32>) -> (u32, u32) {
+    let step = match layout.shape {
+        Shape::Hex { point_up: true } => Vector2::new(
+            super::short_radius(layout.tile_size),
+            layout.tile_size * 0.75,
+        ),
+        Shape::Hex { point_up: false } => Vector2::new(
+            layout.tile_size * 0.75,
+            super::short_radius(layout.tile_size),
+        ),
+        _ => Vector2::new(1.0, 1.0) * layout.cell_size(),
+    };
+    let cover = |extent: f32, origin: f32, step: f32| ((extent - origin) / step).ceil() as u32 + 1;
+    (
+        cover(size.x, layout.origin.x, step.x),
+        cover(size.y, layout.origin.y, step.y),
+    )
+}
+
+/// How far the grid repeats itself, taking the stagger of hex grids into account
+fn period(layout: Layout) -> Vector2<f32> {
+    let size = layout.tile_size;
+    match layout.shape {
+        Shape::Hex { point_up: true } => Vector2::new(super::short_radius(size), size * 1.5),
+        Shape::Hex { point_up: false } => Vector2::new(size * 1.5, super::short_radius(size)),
+        _ => Vector2::new(1.0, 1.0) * layout.cell_size(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calibrates from the centres of `cells` as `actual` lays them out
+    fn calibrate(actual: Layout, cells: &[(u32, u32)]) -> Layout {
+        let mut calibration = match actual.shape {
+            Shape::Square => Calibration::square(),
+            _ => Calibration::hex(),
+        };
+        for &(x, y) in cells {
+            calibration.mark(actual.cell_to_world(Vector2::new(x, y)));
+        }
+        calibration.layout().unwrap()
+    }
+
+    fn assert_matches(actual: Layout, calibrated: Layout, cells: &[(u32, u32)]) {
+        assert_eq!(calibrated.shape, actual.shape);
+        assert!((calibrated.tile_size - actual.tile_size).abs() < 1e-3);
+        let period = period(calibrated);
+        assert!(calibrated.origin.x <= 0.0 && calibrated.origin.x > -period.x);
+        assert!(calibrated.origin.y <= 0.0 && calibrated.origin.y > -period.y);
+        // the marked centres are centres of cells in the calibrated grid too
+        for &(x, y) in cells {
+            let mark = actual.cell_to_world(Vector2::new(x, y));
+            let cell = calibrated.world_to_cell(mark).unwrap();
+            assert!((calibrated.cell_to_world(cell) - mark).magnitude() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn point_up_hexes_from_a_row() {
+        let actual =
+            Layout::new(Shape::Hex { point_up: true }, 80.0).with_origin(Vector2::new(17.0, 31.0));
+        let cells = [(2, 4), (3, 4), (4, 4), (5, 4)];
+        assert_matches(actual, calibrate(actual, &cells), &cells);
+    }
+
+    #[test]
+    fn flat_top_hexes_from_a_column() {
+        let actual =
+            Layout::new(Shape::Hex { point_up: false }, 64.0).with_origin(Vector2::new(-5.0, 12.0));
+        let cells = [(3, 2), (3, 3), (3, 4)];
+        assert_matches(actual, calibrate(actual, &cells), &cells);
+    }
+
+    #[test]
+    fn squares_from_a_column() {
+        let actual = Layout::new(Shape::Square, 50.0).with_origin(Vector2::new(120.0, 7.5));
+        let cells = [(1, 1), (1, 2), (1, 3), (1, 4), (1, 5)];
+        assert_matches(actual, calibrate(actual, &cells), &cells);
+    }
+
+    #[test]
+    fn no_layout_without_two_distinct_marks() {
+        let mut calibration = Calibration::hex();
+        assert_eq!(calibration.layout(), None);
+        calibration.mark(Vector2::new(10.0, 10.0));
+        assert_eq!(calibration.layout(), None);
+        calibration.mark(Vector2::new(10.0, 10.0));
+        assert_eq!(calibration.layout(), None);
+    }
+}
@@ -0,0 +1,221 @@
+use super::layer::TileLayer;
+use super::layout::Layout;
+use crate::fgl::{self, Bindable};
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector4};
+
+/// Width and height of a chunk, in hexes
+pub const CHUNK_SIZE: u32 = 32;
+
+/// A rectangle of the grid with its own instance buffers, so it can be
+/// culled and updated on its own
+pub(super) struct Chunk {
+    origin: Vector2<u32>,
+    size: Vector2<u32>,
+    /// World space bounding box
+    min: Vector2<f32>,
+    max: Vector2<f32>,
+    /// Tile offsets and elevation
+    vbos: [fgl::VertexBuffer; 2],
+    /// Tile buffer and attribute setup for each of the grid's layers, in order
+    layers: Vec<(fgl::VertexBuffer, fgl::VertexAttribObject)>,
+    /// Overlay colours, drawn with the overlay program
+    tints: (fgl::VertexBuffer, fgl::VertexAttribObject),
+    /// Whether any of the tints are visible at all
+    tinted: bool,
+}
+
+impl Chunk {
+    pub(super) fn new(
+        origin: Vector2<u32>,
+        size: Vector2<u32>,
+        layout: Layout,
+        quad: &fgl::VertexBuffer,
+        elevation: &[i32],
+        layers: &[TileLayer],
+        width: u32,
+    ) -> Self {
+        let (min, max) = bounds(layout, origin, size);
+
+        let vbos: [fgl::VertexBuffer; 2] = fgl::VertexBuffer::new_array();
+        let mut chunk = Self {
+            origin,
+            size,
+            min,
+            max,
+            vbos,
+            layers: Vec::new(),
+            tints: (fgl::VertexBuffer::new(), fgl::VertexAttribObject::new()),
+            tinted: false,
+        };
+
+        let offsets: Vec<_> = chunk
+            .cells(width)
+            .map(|(coords, _)| layout.tile_origin(coords))
+            .collect();
+        chunk.vbos[0].alloc_with(
+            &offsets,
+            fgl::AccessFrequency::Static,
+            fgl::AccessType::Draw,
+        );
+        chunk.upload_elevation(elevation, width);
+
+        let (vbo, vao) = &chunk.tints;
+        vao.vertex_attribute_array(
+            quad,
+            fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
+        );
+        vao.vertex_attribute_array(
+            &chunk.vbos[0],
+            fgl::VertexAttribArray::<f32>::with_id(1)
+                .with_components_per_value(2)
+                .with_divisor(1),
+        );
+        vao.vertex_attribute_array(
+            vbo,
+            fgl::VertexAttribArray::<f32>::with_id(2)
+                .with_components_per_value(4)
+                .with_divisor(1),
+        );
+
+        for layer in layers {
+            let vao = fgl::VertexAttribObject::new();
+            vao.vertex_attribute_array(
+                quad,
+                fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
+            );
+            vao.vertex_attribute_array(
+                &chunk.vbos[0],
+                fgl::VertexAttribArray::<f32>::with_id(1)
+                    .with_components_per_value(2)
+                    .with_divisor(1),
+            );
+            vao.vertex_attribute_array(
+                &chunk.vbos[1],
+                fgl::VertexAttribArray::<f32>::with_id(3).with_divisor(1),
+            );
+            let vbo = fgl::VertexBuffer::new();
+            let stride = 2 * std::mem::size_of::<f32>() as i32;
+            vao.vertex_attribute_array(
+                &vbo,
+                fgl::VertexAttribArray::<f32>::with_id(2)
+                    .with_stride(stride)
+                    .with_divisor(1),
+            );
+            vao.vertex_attribute_array(
+                &vbo,
+                fgl::VertexAttribArray::<f32>::with_id(4)
+                    .with_stride(stride)
+                    .with_offset(stride / 2)
+                    .with_divisor(1),
+            );
+            chunk.layers.push((vbo, vao));
+            chunk.upload_layer(chunk.layers.len() - 1, layer, width);
+        }
+        chunk
+    }
+
+    /// Coordinates and row-major grid index of every hex in the chunk, row by row
+    pub(super) fn cells(&self, width: u32) -> impl Iterator<Item = (Vector2<u32>, usize)> {
+        let (origin, size) = (self.origin, self.size);
+        (0..size.y).flat_map(move |y| {
+            (0..size.x).map(move |x| {
+                let coords = origin + Vector2::new(x, y);
+                (coords, (coords.x + width * coords.y) as usize)
+            })
+        })
+    }
+
+    pub(super) fn upload_elevation(&mut self, elevation: &[i32], width: u32) {
+        let data: Vec<_> = self
+            .cells(width)
+            .map(|(_, idx)| elevation[idx] as f32)
+            .collect();
+        self.vbos[1].alloc_with(&data, fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+    }
+
+    pub(super) fn upload_layer(&mut self, n: usize, layer: &TileLayer, width: u32) {
+        let data: Vec<_> = self
+            .cells(width)
+            .flat_map(|(_, idx)| IntoIterator::into_iter(layer.instance(idx)))
+            .collect();
+        self.layers[n]
+            .0
+            .alloc_with(&data, fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+    }
+
+    /// `tints` is one colour per hex, in the order `cells` gives them
+    pub(super) fn upload_tints(&mut self, tints: &[Vector4<f32>]) {
+        self.tinted = tints.iter().any(|tint| tint.w > 0.0);
+        self.tints
+            .0
+            .alloc_with(tints, fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+    }
+
+    /// Whether any of the chunk might be visible in the world space rectangle
+    pub(super) fn intersects(&self, min: Vector2<f32>, max: Vector2<f32>) -> bool {
+        overlaps((self.min, self.max), (min, max))
+    }
+
+    /// Expects the grid program to be bound, with the layer's uniforms set
+    pub(super) unsafe fn draw(&self, program: &fgl::Program, n: usize) {
+        program.uniform_ivec2("chunk_origin", self.origin.cast().unwrap());
+        program.uniform_i32("chunk_width", self.size.x as i32);
+        self.layers[n].1.bind();
+        gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, (self.size.x * self.size.y) as i32);
+    }
+
+    /// Expects the overlay program to be bound
+    pub(super) unsafe fn draw_tints(&self) {
+        if !self.tinted {
+            return;
+        }
+        self.tints.1.bind();
+        gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, (self.size.x * self.size.y) as i32);
+    }
+}
+
+/// Origin and size of each chunk of a grid of `dimensions`, row by row
+pub(super) fn areas(dimensions: (u32, u32)) -> Vec<(Vector2<u32>, Vector2<u32>)> {
+    let (width, height) = dimensions;
+    let mut areas = Vec::new();
+    for y in (0..height).step_by(CHUNK_SIZE as usize) {
+        for x in (0..width).step_by(CHUNK_SIZE as usize) {
+            let size = Vector2::new(CHUNK_SIZE.min(width - x), CHUNK_SIZE.min(height - y));
+            areas.push((Vector2::new(x, y), size));
+        }
+    }
+    areas
+}
+
+/// World space bounding box of the chunk at `origin`
+pub(super) fn bounds(
+    layout: Layout,
+    origin: Vector2<u32>,
+    size: Vector2<u32>,
+) -> (Vector2<f32>, Vector2<f32>) {
+    let cell = layout.cell_size();
+    // generous enough to cover the stagger on hex grids
+    let min = layout.tile_origin(origin) - Vector2::new(cell, cell);
+    let max =
+        layout.tile_origin(origin + size - Vector2::new(1, 1)) + Vector2::new(cell, cell) * 2.0;
+    (min, max)
+}
+
+/// The world space rectangle on screen when drawing with `projection`
+pub(super) fn view_bounds(projection: Matrix4<f32>) -> Option<(Vector2<f32>, Vector2<f32>)> {
+    // from the corners of clip space
+    let inverse = projection.invert()?;
+    let corner = |x: f32, y: f32| {
+        let world = inverse * Vector4::new(x, y, 0.0, 1.0);
+        Vector2::new(world.x / world.w, world.y / world.w)
+    };
+    let (a, b) = (corner(-1.0, -1.0), corner(1.0, 1.0));
+    let min = Vector2::new(a.x.min(b.x), a.y.min(b.y));
+    let max = Vector2::new(a.x.max(b.x), a.y.max(b.y));
+    Some((min, max))
+}
+
+/// Whether two world space rectangles, given as their corners, overlap
+pub(super) fn overlaps(a: (Vector2<f32>, Vector2<f32>), b: (Vector2<f32>, Vector2<f32>)) -> bool {
+    a.0.x <= b.1.x && b.0.x <= a.1.x && a.0.y <= b.1.y && b.0.y <= a.1.y
+}
@@ -0,0 +1,443 @@
+use cgmath::Vector2;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Axial hex coordinates
+///
+/// The third cube coordinate is implied by `q + r + s = 0`. Offset
+/// coordinates, as used by the grid and tokens, depend on the orientation of
+/// the grid, so converting needs to know whether the grid is point-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hex {
+    pub q: i32,
+    pub r: i32,
+}
+
+/// Neighbour directions, clockwise
+///
+/// On point-up grids direction 0 is east, on flat-top grids it is north east.
+pub const DIRECTIONS: [Hex; 6] = [
+    Hex::new(1, 0),
+    Hex::new(1, -1),
+    Hex::new(0, -1),
+    Hex::new(-1, 0),
+    Hex::new(-1, 1),
+    Hex::new(0, 1),
+];
+
+impl Hex {
+    pub const ORIGIN: Hex = Hex::new(0, 0);
+
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    fn from_cube(q: i32, r: i32, s: i32) -> Self {
+        debug_assert_eq!(q + r + s, 0);
+        Self { q, r }
+    }
+
+    pub fn s(self) -> i32 {
+        -self.q - self.r
+    }
+
+    pub fn from_offset(coords: Vector2<u32>, point_up: bool) -> Self {
+        let (col, row) = (coords.x as i32, coords.y as i32);
+        if point_up {
+            Self::new(col - (row - (row & 1)) / 2, row)
+        } else {
+            Self::new(col, row - (col - (col & 1)) / 2)
+        }
+    }
+
+    /// Offset coordinates of this hex, if it has any
+    pub fn to_offset(self, point_up: bool) -> Option<Vector2<u32>> {
+        let (col, row) = if point_up {
+            (self.q + (self.r - (self.r & 1)) / 2, self.r)
+        } else {
+            (self.q, self.r + (self.q - (self.q & 1)) / 2)
+        };
+        if col < 0 || row < 0 {
+            None
+        } else {
+            Some(Vector2::new(col as u32, row as u32))
+        }
+    }
+
+    pub fn direction(dir: usize) -> Self {
+        DIRECTIONS[dir % 6]
+    }
+
+    pub fn neighbour(self, dir: usize) -> Self {
+        self + Self::direction(dir)
+    }
+
+    pub fn neighbours(self) -> [Hex; 6] {
+        let mut neighbours = DIRECTIONS;
+        for neighbour in &mut neighbours {
+            *neighbour = *neighbour + self;
+        }
+        neighbours
+    }
+
+    /// Distance from the origin, in hexes
+    pub fn length(self) -> u32 {
+        ((self.q.abs() + self.r.abs() + self.s().abs()) / 2) as u32
+    }
+
+    pub fn distance(self, other: Hex) -> u32 {
+        (self - other).length()
+    }
+
+    /// All hexes exactly `radius` away, walking clockwise
+    pub fn ring(self, radius: u32) -> Vec<Hex> {
+        if radius == 0 {
+            return vec![self];
+        }
+        let mut ring = Vec::with_capacity(6 * radius as usize);
+        let mut hex = self + Self::direction(4) * radius as i32;
+        for dir in 0..6 {
+            for _ in 0..radius {
+                ring.push(hex);
+                hex = hex.neighbour(dir);
+            }
+        }
+        ring
+    }
+
+    /// All hexes within `radius`, ordered ring by ring outwards
+    pub fn spiral(self, radius: u32) -> Vec<Hex> {
+        (0..=radius).flat_map(|r| self.ring(r)).collect()
+    }
+
+    /// Hexes crossed by the straight line between the centres of the two hexes
+    ///
+    /// Where the line runs exactly along an edge, or through a corner, both
+    /// candidate hexes are reported as a `LineStep::Edge`.
+    pub fn line_to(self, other: Hex) -> Vec<LineStep> {
+        // nudging the line both ways only gives different hexes where it is
+        // exactly on a boundary. Work relative to `self` to keep the floats small
+        const NUDGE: FractionalHex = FractionalHex { q: 1e-4, r: 2e-4 };
+        let n = self.distance(other);
+        let end = FractionalHex::from(other - self);
+        (0..=n)
+            .map(|i| {
+                let t = if n == 0 { 0.0 } else { i as f32 / n as f32 };
+                let a = NUDGE.lerp(end + NUDGE, t).round() + self;
+                let b = (-NUDGE).lerp(end - NUDGE, t).round() + self;
+                if a == b {
+                    LineStep::Hex(a)
+                } else {
+                    LineStep::Edge(a, b)
+                }
+            })
+            .collect()
+    }
+
+    /// Rotates 60° clockwise around the origin
+    pub fn rotate_cw(self) -> Self {
+        Self::from_cube(-self.s(), -self.q, -self.r)
+    }
+
+    /// Rotates 60° counter-clockwise around the origin
+    pub fn rotate_ccw(self) -> Self {
+        Self::from_cube(-self.r, -self.s(), -self.q)
+    }
+
+    /// Rotates by `steps` 60° steps clockwise (negative for counter-clockwise) around `centre`
+    pub fn rotate_around(self, centre: Hex, steps: i32) -> Self {
+        let mut hex = self - centre;
+        for _ in 0..steps.rem_euclid(6) {
+            hex = hex.rotate_cw();
+        }
+        hex + centre
+    }
+
+    /// Reflects across the axis through `centre` along which `q` is constant
+    pub fn reflect_q(self, centre: Hex) -> Self {
+        let hex = self - centre;
+        Self::from_cube(hex.q, hex.s(), hex.r) + centre
+    }
+
+    /// Reflects across the axis through `centre` along which `r` is constant
+    pub fn reflect_r(self, centre: Hex) -> Self {
+        let hex = self - centre;
+        Self::from_cube(hex.s(), hex.r, hex.q) + centre
+    }
+
+    /// Reflects across the axis through `centre` along which `s` is constant
+    pub fn reflect_s(self, centre: Hex) -> Self {
+        let hex = self - centre;
+        Self::from_cube(hex.r, hex.q, hex.s()) + centre
+    }
+}
+
+impl Add for Hex {
+    type Output = Hex;
+    fn add(self, other: Hex) -> Hex {
+        Hex::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for Hex {
+    type Output = Hex;
+    fn sub(self, other: Hex) -> Hex {
+        Hex::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Neg for Hex {
+    type Output = Hex;
+    fn neg(self) -> Hex {
+        Hex::new(-self.q, -self.r)
+    }
+}
+
+impl Mul<i32> for Hex {
+    type Output = Hex;
+    fn mul(self, k: i32) -> Hex {
+        Hex::new(self.q * k, self.r * k)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineStep {
+    Hex(Hex),
+    /// The line runs along the edge between, or through a corner of, these hexes
+    Edge(Hex, Hex),
+}
+
+impl LineStep {
+    pub fn hexes(self) -> impl Iterator<Item = Hex> {
+        let (a, b) = match self {
+            LineStep::Hex(hex) => (hex, None),
+            LineStep::Edge(a, b) => (a, Some(b)),
+        };
+        std::iter::once(a).chain(b)
+    }
+}
+
+/// Axial coordinates that have not been rounded to a hex yet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FractionalHex {
+    pub q: f32,
+    pub r: f32,
+}
+
+impl FractionalHex {
+    pub fn new(q: f32, r: f32) -> Self {
+        Self { q, r }
+    }
+
+    pub fn s(self) -> f32 {
+        -self.q - self.r
+    }
+
+    pub fn lerp(self, other: FractionalHex, t: f32) -> Self {
+        Self::new(
+            self.q + (other.q - self.q) * t,
+            self.r + (other.r - self.r) * t,
+        )
+    }
+
+    /// The hex containing these coordinates
+    pub fn round(self) -> Hex {
+        let (mut q, mut r, s) = (self.q.round(), self.r.round(), self.s().round());
+        let (dq, dr, ds) = ((q - self.q).abs(), (r - self.r).abs(), (s - self.s()).abs());
+        if dq > dr && dq > ds {
+            q = -r - s;
+        } else if dr > ds {
+            r = -q - s;
+        }
+        Hex::new(q as i32, r as i32)
+    }
+}
+
+impl From<Hex> for FractionalHex {
+    fn from(hex: Hex) -> Self {
+        Self::new(hex.q as f32, hex.r as f32)
+    }
+}
+
+impl Add for FractionalHex {
+    type Output = FractionalHex;
+    fn add(self, other: FractionalHex) -> FractionalHex {
+        FractionalHex::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for FractionalHex {
+    type Output = FractionalHex;
+    fn sub(self, other: FractionalHex) -> FractionalHex {
+        FractionalHex::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Neg for FractionalHex {
+    type Output = FractionalHex;
+    fn neg(self) -> FractionalHex {
+        FractionalHex::new(-self.q, -self.r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTRE: Hex = Hex::new(3, -2);
+
+    fn is_connected(hexes: &[Hex]) -> bool {
+        hexes.windows(2).all(|pair| pair[0].distance(pair[1]) == 1)
+    }
+
+    #[test]
+    fn rings_walk_around_the_centre() {
+        assert_eq!(CENTRE.ring(0), vec![CENTRE]);
+        for radius in 1..4 {
+            let mut ring = CENTRE.ring(radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|hex| hex.distance(CENTRE) == radius));
+            ring.push(ring[0]);
+            assert!(is_connected(&ring));
+            ring.sort();
+            ring.dedup();
+            assert_eq!(ring.len(), 6 * radius as usize);
+        }
+    }
+
+    #[test]
+    fn spirals_hold_every_hex_within_the_radius() {
+        let spiral = CENTRE.spiral(3);
+        assert_eq!(spiral.len(), 37);
+        assert_eq!(spiral[0], CENTRE);
+        assert!(spiral
+            .windows(2)
+            .all(|pair| pair[0].distance(CENTRE) <= pair[1].distance(CENTRE)));
+        let mut unique = spiral.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), spiral.len());
+    }
+
+    #[test]
+    fn lines_along_a_direction_cross_one_hex_per_step() {
+        for dir in 0..6 {
+            let end = CENTRE + Hex::direction(dir) * 4;
+            let line = CENTRE.line_to(end);
+            let hexes: Vec<_> = line
+                .iter()
+                .map(|step| match step {
+                    LineStep::Hex(hex) => *hex,
+                    LineStep::Edge(a, b) => panic!("edge between {:?} and {:?}", a, b),
+                })
+                .collect();
+            assert_eq!(hexes.len(), 5);
+            assert_eq!(hexes.first(), Some(&CENTRE));
+            assert_eq!(hexes.last(), Some(&end));
+            assert!(is_connected(&hexes));
+        }
+        assert_eq!(CENTRE.line_to(CENTRE), vec![LineStep::Hex(CENTRE)]);
+    }
+
+    #[test]
+    fn lines_along_an_edge_report_both_hexes() {
+        // halfway to (1, 1) is the middle of the edge between (1, 0) and (0, 1)
+        let end = CENTRE + Hex::new(1, 1);
+        let line = CENTRE.line_to(end);
+        assert_eq!(line.len(), 3);
+        assert_eq!(line[0], LineStep::Hex(CENTRE));
+        assert_eq!(line[2], LineStep::Hex(end));
+        let mut middle: Vec<_> = line[1].hexes().collect();
+        middle.sort();
+        assert_eq!(
+            middle,
+            vec![CENTRE + Hex::new(0, 1), CENTRE + Hex::new(1, 0)]
+        );
+    }
+
+    #[test]
+    fn lines_through_a_hex_are_nudged_off_its_edges() {
+        // a knight's move passes through hexes, never along an edge between them
+        let end = CENTRE + Hex::new(2, 1);
+        let line = CENTRE.line_to(end);
+        assert_eq!(line.len(), 4);
+        assert!(line.iter().all(|step| matches!(step, LineStep::Hex(_))));
+        let hexes: Vec<_> = line.iter().flat_map(|step| step.hexes()).collect();
+        assert!(is_connected(&hexes));
+    }
+
+    #[test]
+    fn rotating_steps_through_the_directions() {
+        for dir in 0..6 {
+            assert_eq!(Hex::direction(dir).rotate_cw(), Hex::direction(dir + 1));
+            assert_eq!(Hex::direction(dir + 1).rotate_ccw(), Hex::direction(dir));
+        }
+        let hex = Hex::new(2, -5);
+        assert_eq!(hex.rotate_cw().rotate_ccw(), hex);
+        assert_eq!(hex.rotate_around(CENTRE, 6), hex);
+        assert_eq!(hex.rotate_around(CENTRE, -1), hex.rotate_around(CENTRE, 5));
+        for steps in 0..6 {
+            let rotated = hex.rotate_around(CENTRE, steps);
+            assert_eq!(rotated.distance(CENTRE), hex.distance(CENTRE));
+        }
+        let offset = CENTRE + Hex::direction(0) * 2;
+        assert_eq!(
+            offset.rotate_around(CENTRE, 2),
+            CENTRE + Hex::direction(2) * 2
+        );
+    }
+
+    #[test]
+    fn reflecting_keeps_the_axis_coordinate() {
+        let hex = Hex::new(2, -5);
+        let (reflected_q, reflected_r, reflected_s) = (
+            hex.reflect_q(CENTRE) - CENTRE,
+            hex.reflect_r(CENTRE) - CENTRE,
+            hex.reflect_s(CENTRE) - CENTRE,
+        );
+        let relative = hex - CENTRE;
+        assert_eq!(reflected_q.q, relative.q);
+        assert_eq!(reflected_r.r, relative.r);
+        assert_eq!(reflected_s.s(), relative.s());
+        assert_ne!(reflected_q, relative);
+        assert_eq!(hex.reflect_q(CENTRE).reflect_q(CENTRE), hex);
+        assert_eq!(hex.reflect_r(CENTRE).reflect_r(CENTRE), hex);
+        assert_eq!(hex.reflect_s(CENTRE).reflect_s(CENTRE), hex);
+    }
+
+    #[test]
+    fn offset_coordinates_round_trip() {
+        for &point_up in &[true, false] {
+            for y in 0..6 {
+                for x in 0..6 {
+                    let coords = Vector2::new(x, y);
+                    let hex = Hex::from_offset(coords, point_up);
+                    assert_eq!(hex.to_offset(point_up), Some(coords));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offset_coordinates_follow_the_stagger() {
+        let distance = |a: (u32, u32), b: (u32, u32), point_up| {
+            let a = Hex::from_offset(Vector2::new(a.0, a.1), point_up);
+            a.distance(Hex::from_offset(Vector2::new(b.0, b.1), point_up))
+        };
+        // odd rows are shifted right
+        assert_eq!(distance((0, 1), (0, 0), true), 1);
+        assert_eq!(distance((0, 1), (1, 0), true), 1);
+        assert_eq!(distance((0, 2), (1, 1), true), 2);
+        // odd columns are shifted up
+        assert_eq!(distance((1, 0), (0, 0), false), 1);
+        assert_eq!(distance((1, 0), (0, 1), false), 1);
+        assert_eq!(distance((2, 0), (1, 1), false), 2);
+    }
+
+    #[test]
+    fn hexes_left_of_or_below_the_grid_have_no_offset() {
+        assert_eq!(Hex::new(-1, 0).to_offset(true), None);
+        assert_eq!(Hex::new(0, -1).to_offset(true), None);
+        assert_eq!(Hex::new(-1, 0).to_offset(false), None);
+        assert_eq!(Hex::new(1, -1).to_offset(false), None);
+    }
+}
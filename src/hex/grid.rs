@@ -1,26 +1,47 @@
-use crate::fgl::{self, Bindable};
+use super::background::Background;
+use super::chunk::{self, Chunk, CHUNK_SIZE};
+use super::coord::Hex;
+use super::layer::{Orientation, TileLayer};
+use super::layout::{Layout, Shape};
+use super::overlay::HexOverlay;
+use super::pick::HEX_PASS;
+use super::tile::Tile;
+use crate::fgl;
+use cgmath::Vector2;
 use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Name of the bottom layer, which `with_tiles`, `with_grid_contents` and
+/// `with_orientations` fill
+pub const BASE_LAYER: &str = "base";
 
 pub struct HexGridBuilder<'a> {
-    point_up: bool,
-    tiles: &'a [image::DynamicImage],
+    shape: Shape,
+    tile_size: Option<u32>,
+    tiles: &'a [Tile],
     dimensions: (u32, u32),
     grid_contents: Option<Vec<isize>>,
+    orientations: Option<Vec<Orientation>>,
+    layers: Vec<(String, &'a [Tile])>,
 }
 
 impl<'a> Default for HexGridBuilder<'a> {
     fn default() -> Self {
         Self {
-            point_up: false,
+            shape: Shape::Hex { point_up: false },
+            tile_size: None,
             tiles: &[],
             dimensions: (0, 0),
             grid_contents: None,
+            orientations: None,
+            layers: Vec::new(),
         }
     }
 }
 
 impl<'a> HexGridBuilder<'a> {
-    pub fn with_tiles(mut self, tiles: &'a [image::DynamicImage]) -> Self {
+    pub fn with_tiles(mut self, tiles: &'a [Tile]) -> Self {
         self.tiles = tiles;
         self
     }
@@ -32,7 +53,11 @@ impl<'a> HexGridBuilder<'a> {
         );
         self
     }
-    pub fn build(mut self) -> HexGrid {
+    pub fn with_orientations(mut self, orientations: &[Orientation]) -> Self {
+        self.orientations = Some(orientations.to_vec());
+        self
+    }
+    pub fn build(self) -> HexGrid {
         assert_eq!(
             (self.dimensions.0 * self.dimensions.1) as usize,
             self.grid_contents
@@ -40,81 +65,86 @@ impl<'a> HexGridBuilder<'a> {
                 .map(|x| x.len())
                 .unwrap_or((self.dimensions.0 * self.dimensions.1) as usize)
         );
-        let tile_size = self
-            .tiles
-            .iter()
-            .map(|image| u32::max(image.dimensions().0, image.dimensions().1))
-            .max()
-            .unwrap_or(0);
-        let mut texture = fgl::texture::Texture2D::with_dimensions(
-            tile_size as i32 * self.tiles.len() as i32,
-            tile_size as i32,
-            fgl::texture::Format::Rgba,
+        assert_eq!(
+            (self.dimensions.0 * self.dimensions.1) as usize,
+            self.orientations
+                .as_ref()
+                .map(|x| x.len())
+                .unwrap_or((self.dimensions.0 * self.dimensions.1) as usize)
         );
-        for (n, image) in self.tiles.iter().enumerate() {
-            texture.replace_rect((n as u32 * tile_size) as i32, 0, image.clone());
-        }
-
-        let vao = fgl::VertexAttribObject::new();
+        let tile_size = self.tile_size.unwrap_or_else(|| {
+            self.tiles
+                .iter()
+                .map(Tile::first_frame)
+                .map(|image| u32::max(image.dimensions().0, image.dimensions().1))
+                .max()
+                .unwrap_or(0)
+        });
+        let layout = Layout::new(self.shape, tile_size as f32);
 
-        let mut vbos: [fgl::VertexBuffer; 3] = fgl::VertexBuffer::new_array();
-        vbos[0].alloc_with(
+        let mut quad = fgl::VertexBuffer::new();
+        quad.alloc_with(
             &fgl::consts::QUAD,
             fgl::AccessFrequency::Static,
             fgl::AccessType::Draw,
         );
-        vao.vertex_attribute_array(
-            &vbos[0],
-            fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
-        );
-
-        let offsets = super::grid_coords(self.dimensions.0, self.dimensions.1, tile_size as f32);
-        vbos[1].alloc_with(
-            &offsets,
-            fgl::AccessFrequency::Static,
-            fgl::AccessType::Draw,
-        );
-        vao.vertex_attribute_array(
-            &vbos[1],
-            fgl::VertexAttribArray::<f32>::with_id(1)
-                .with_components_per_value(2)
-                .with_divisor(6),
-        );
 
         let dims = self.dimensions;
-        self.grid_contents = Some(
-            self.grid_contents
-                .unwrap_or_else(|| vec![0isize; (dims.0 * dims.1) as usize]),
-        );
-        vao.vertex_attribute_array(
-            &vbos[2],
-            fgl::VertexAttribArray::<f32>::with_id(2).with_divisor(6),
-        );
-        vbos[2].alloc_with(
-            &(self
-                .grid_contents
-                .as_ref()
-                .unwrap()
-                .iter()
-                .map(|x| *x as f32)
-                .collect::<Vec<_>>()),
-            fgl::AccessFrequency::Dynamic,
-            fgl::AccessType::Draw,
-        );
-
-        HexGrid {
+        let cells = (dims.0 * dims.1) as usize;
+        let mut grid = HexGrid {
             dimensions: self.dimensions,
-            tile_size,
-            vbos,
-            vao,
-            texture,
-            tilecount: self.tiles.len() as u32,
-            grid_contents: self.grid_contents.unwrap(),
+            layout,
+            background: None,
+            overlay: HexOverlay::new(layout),
+            quad,
+            chunks: Vec::new(),
+            layers: Vec::new(),
+            terrain: vec![Terrain::default(); cells],
+            elevation: vec![0i32; cells],
+            started: Instant::now(),
+        };
+        let base = self.grid_contents.unwrap_or_else(|| vec![0isize; cells]);
+        let orientations = self
+            .orientations
+            .unwrap_or_else(|| vec![Orientation::default(); cells]);
+        grid.layers
+            .push(TileLayer::new(BASE_LAYER, self.tiles, base, orientations));
+        for (name, tiles) in &self.layers {
+            let contents = vec![-1; cells];
+            let orientations = vec![Orientation::default(); cells];
+            grid.layers
+                .push(TileLayer::new(name, tiles, contents, orientations));
         }
+        grid.rebuild_chunks();
+        grid
+    }
+
+    /// Adds an empty layer above the ones added before
+    pub fn with_layer(mut self, name: &str, tiles: &'a [Tile]) -> Self {
+        self.layers.push((name.to_string(), tiles));
+        self
     }
 
     pub fn point_up(mut self) -> Self {
-        self.point_up = true;
+        self.shape = Shape::Hex { point_up: true };
+        self
+    }
+
+    pub fn square(mut self) -> Self {
+        self.shape = Shape::Square;
+        self
+    }
+
+    /// Nothing is drawn for gridless maps, and dimensions count lattice
+    /// points rather than tiles
+    pub fn gridless(mut self) -> Self {
+        self.shape = Shape::Gridless;
+        self
+    }
+
+    /// Overrides the tile size, which otherwise comes from the largest tile image
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = Some(tile_size);
         self
     }
 
@@ -126,39 +156,667 @@ impl<'a> HexGridBuilder<'a> {
 
 pub struct HexGrid {
     dimensions: (u32, u32),
-    tile_size: u32,
-    vbos: [fgl::VertexBuffer; 3],
-    vao: fgl::VertexAttribObject,
-    texture: fgl::texture::Texture2D,
-    tilecount: u32,
-    grid_contents: Vec<isize>,
+    layout: Layout,
+    background: Option<Background>,
+    overlay: HexOverlay,
+    quad: fgl::VertexBuffer,
+    /// Row by row, `CHUNK_SIZE` hexes square except along the top and right
+    chunks: Vec<Chunk>,
+    /// Bottom to top
+    layers: Vec<TileLayer>,
+    terrain: Vec<Terrain>,
+    elevation: Vec<i32>,
+    /// What animated tiles count their frames from
+    started: Instant,
+}
+
+/// Rules data attached to each hex, independent of the tile drawn there
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Terrain {
+    pub blocks_sight: bool,
+    /// Cost of moving into the hex, `None` if it is impassable
+    pub movement_cost: Option<u32>,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            blocks_sight: false,
+            movement_cost: Some(1),
+        }
+    }
 }
 
 impl HexGrid {
     pub unsafe fn draw(&self, program: &fgl::Program, projection: cgmath::Matrix4<f32>) {
+        self.draw_layers(program, projection, |_| true);
+    }
+
+    /// Draws what players may see, leaving out GM-only layers
+    pub unsafe fn draw_for_players(
+        &self,
+        program: &fgl::Program,
+        projection: cgmath::Matrix4<f32>,
+    ) {
+        self.draw_layers(program, projection, |layer| !layer.gm_only);
+    }
+
+    unsafe fn draw_layers(
+        &self,
+        program: &fgl::Program,
+        projection: cgmath::Matrix4<f32>,
+        show: impl Fn(&TileLayer) -> bool,
+    ) {
+        if let Some(background) = &self.background {
+            background.draw(projection);
+        }
+        if self.layout.shape == Shape::Gridless {
+            return;
+        }
         program.bind();
         program.uniform_mat4("projection", &projection);
-        program.uniform_vec2(
-            "size",
-            [self.tile_size as f32, self.tile_size as f32].into(),
-        );
-        program.uniform_f32("ntiles", self.tilecount as f32);
-        program.uniform_u32("renderpass", 0);
-        self.vao.bind();
-        self.texture.bind(0);
-        gl::DrawArraysInstanced(
-            gl::TRIANGLES,
-            0,
-            6i32,
-            (self.dimensions.0 * self.dimensions.1 * 6) as i32,
-        );
+        let size = self.layout.cell_size();
+        program.uniform_vec2("size", [size, size].into());
+        program.uniform_i32("shape", self.layout.shader_shape());
+        program.uniform_u32("renderpass", HEX_PASS);
+        // wrapped every hour to keep float milliseconds precise
+        let time = self.started.elapsed().as_millis() % 3_600_000;
+        program.uniform_f32("time", time as f32);
+
+        let visible = self.visible_chunks(projection);
+        for (n, layer) in self.layers.iter().enumerate() {
+            if !layer.visible || !show(layer) {
+                continue;
+            }
+            layer.bind(program);
+            for chunk in &visible {
+                chunk.draw(program, n);
+            }
+        }
+    }
+
+    /// Whether a visible layer has animated tiles, so the grid needs redrawing as time passes
+    pub fn animated(&self) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| layer.visible && layer.animated())
+    }
+
+    /// Draws the overlay's tints with the overlay program, on top of everything drawn before
+    pub unsafe fn draw_overlay(&self, program: &fgl::Program, projection: cgmath::Matrix4<f32>) {
+        program.bind();
+        program.uniform_mat4("projection", &projection);
+        let size = self.layout.cell_size();
+        program.uniform_vec2("size", [size, size].into());
+        program.uniform_i32("shape", self.layout.shader_shape());
+        for chunk in self.visible_chunks(projection) {
+            chunk.draw_tints();
+        }
     }
 
-    pub fn update_tile(&mut self, idx: (usize, usize), tile: Option<usize>) {
-        let idx = idx.1 + self.dimensions.1 as usize * idx.0;
-        let tile = tile.map(|x| x as isize).unwrap_or(-1);
-        self.vbos[2].map_data().put(idx, tile as f32);
-        self.vbos[2].unmap_data();
-        self.grid_contents[idx] = tile;
+    /// Chunks that might be on screen when drawing with `projection`
+    fn visible_chunks(&self, projection: cgmath::Matrix4<f32>) -> Vec<&Chunk> {
+        let (min, max) = match chunk::view_bounds(projection) {
+            Some(view) => view,
+            None => return Vec::new(),
+        };
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.intersects(min, max))
+            .collect()
+    }
+
+    pub fn overlay(&self) -> &HexOverlay {
+        &self.overlay
+    }
+
+    /// Changes show up once `update_overlay` is called
+    pub fn overlay_mut(&mut self) -> &mut HexOverlay {
+        &mut self.overlay
+    }
+
+    /// Uploads the chunks whose tints changed since the last call
+    pub fn update_overlay(&mut self) {
+        let mut touched: Vec<_> = self
+            .overlay
+            .take_changed()
+            .into_iter()
+            .filter(|coords| self.contains(*coords))
+            .map(|coords| self.chunk_index(coords))
+            .collect();
+        touched.sort_unstable();
+        touched.dedup();
+        for chunk in touched {
+            self.upload_tints(chunk);
+        }
+    }
+
+    fn upload_tints(&mut self, chunk: usize) {
+        let tints: Vec<_> = self.chunks[chunk]
+            .cells(self.dimensions.0)
+            .map(|(coords, _)| self.overlay.composite(coords))
+            .collect();
+        self.chunks[chunk].upload_tints(&tints);
+    }
+
+    /// Layers from the bottom up
+    pub fn layers(&self) -> impl Iterator<Item = &TileLayer> {
+        self.layers.iter()
+    }
+
+    /// For changing a layer's visibility or opacity
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|layer| layer.name() == name)
+    }
+
+    /// Adds an empty layer on top of the others
+    pub fn add_layer(
+        &mut self,
+        name: &str,
+        tiles: &[Tile],
+    ) -> Result<(), GridError> {
+        if self.layers.iter().any(|layer| layer.name() == name) {
+            return Err(GridError::LayerExists(name.to_string()));
+        }
+        let contents = vec![-1; self.terrain.len()];
+        let orientations = vec![Orientation::default(); self.terrain.len()];
+        self.layers
+            .push(TileLayer::new(name, tiles, contents, orientations));
+        self.rebuild_chunks();
+        Ok(())
+    }
+
+    pub fn remove_layer(&mut self, name: &str) -> Result<(), GridError> {
+        let idx = self.layer_index(name)?;
+        self.layers.remove(idx);
+        self.rebuild_chunks();
+        Ok(())
+    }
+
+    /// Moves a layer to `position` in the stack, 0 being the bottom
+    pub fn move_layer(&mut self, name: &str, position: usize) -> Result<(), GridError> {
+        let idx = self.layer_index(name)?;
+        let layer = self.layers.remove(idx);
+        self.layers.insert(position.min(self.layers.len()), layer);
+        self.rebuild_chunks();
+        Ok(())
+    }
+
+    fn layer_index(&self, name: &str) -> Result<usize, GridError> {
+        self.layers
+            .iter()
+            .position(|layer| layer.name() == name)
+            .ok_or_else(|| GridError::NoSuchLayer(name.to_string()))
+    }
+
+    /// The hex under a world position, if it lies on the grid
+    pub fn hex_at(&self, world: cgmath::Vector2<f32>) -> Option<cgmath::Vector2<u32>> {
+        self.layout
+            .world_to_cell(world)
+            .filter(|coords| self.contains(*coords))
+    }
+
+    pub fn hex(&self, coords: cgmath::Vector2<u32>) -> Hex {
+        self.layout.hex(coords)
+    }
+
+    /// Offset coordinates of `hex`, if it lies on the grid
+    pub fn offset_coords(&self, hex: Hex) -> Option<cgmath::Vector2<u32>> {
+        self.layout
+            .offset(hex)
+            .filter(|coords| self.contains(*coords))
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Moves and resizes the cells, to line them up with a background for one
+    ///
+    /// Anything else holding a copy of the layout needs the new one as well.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.overlay.set_layout(layout);
+        self.rebuild_chunks();
+    }
+
+    /// Lower left and upper right corners of the smallest rectangle around
+    /// every cell and the background, `None` if there is neither
+    pub fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let (width, height) = self.dimensions;
+        // staggering never moves an inner cell past the ones on the edges
+        let mut cells = Vec::new();
+        if width > 0 && height > 0 {
+            for y in 0..height {
+                cells.extend(&[Vector2::new(0, y), Vector2::new(width - 1, y)]);
+            }
+            for x in 0..width {
+                cells.extend(&[Vector2::new(x, 0), Vector2::new(x, height - 1)]);
+            }
+        }
+        let background = self
+            .background
+            .iter()
+            .flat_map(|background| vec![Vector2::new(0.0, 0.0), background.size()]);
+        cells
+            .into_iter()
+            .flat_map(|coords| self.layout.corners(coords))
+            .chain(background)
+            .fold(None, |bounds, point| {
+                let (min, max) = bounds.unwrap_or((point, point));
+                Some((
+                    Vector2::new(min.x.min(point.x), min.y.min(point.y)),
+                    Vector2::new(max.x.max(point.x), max.y.max(point.y)),
+                ))
+            })
+    }
+
+    pub fn background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+
+    /// Replaces the image drawn under the tiles
+    pub fn set_background(&mut self, background: Option<Background>) {
+        self.background = background;
+    }
+
+    /// Grows the grid at the right and top until it covers the whole background
+    pub fn cover_background(&mut self) {
+        let size = match &self.background {
+            Some(background) => background.size(),
+            None => return,
+        };
+        let (width, height) = super::calibration::dimensions_to_cover(self.layout, size);
+        let dimensions = (width.max(self.dimensions.0), height.max(self.dimensions.1));
+        if dimensions != self.dimensions {
+            self.reshape(dimensions, (0, 0));
+        }
+    }
+
+    pub fn terrain(&self, coords: cgmath::Vector2<u32>) -> Option<Terrain> {
+        self.contains(coords).then(|| self.terrain[self.index(coords)])
+    }
+
+    pub fn set_terrain(
+        &mut self,
+        coords: cgmath::Vector2<u32>,
+        terrain: Terrain,
+    ) -> Result<(), GridError> {
+        let idx = self.checked_index(coords)?;
+        self.terrain[idx] = terrain;
+        Ok(())
+    }
+
+    /// Height of the ground at `coords`, in the same units as token sizes
+    pub fn elevation(&self, coords: cgmath::Vector2<u32>) -> Option<i32> {
+        self.contains(coords).then(|| self.elevation[self.index(coords)])
+    }
+
+    pub fn set_elevation(
+        &mut self,
+        coords: cgmath::Vector2<u32>,
+        elevation: i32,
+    ) -> Result<(), GridError> {
+        let idx = self.checked_index(coords)?;
+        self.elevation[idx] = elevation;
+        let chunk = self.chunk_index(coords);
+        self.chunks[chunk].upload_elevation(&self.elevation, self.dimensions.0);
+        Ok(())
+    }
+
+    /// Changes the elevation of many hexes, uploading each chunk touched once
+    ///
+    /// Nothing is changed if any of the hexes is off the grid.
+    pub fn set_elevations(
+        &mut self,
+        elevations: impl IntoIterator<Item = (cgmath::Vector2<u32>, i32)>,
+    ) -> Result<(), GridError> {
+        let changes = elevations
+            .into_iter()
+            .map(|(coords, elevation)| Ok((self.checked_index(coords)?, coords, elevation)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut touched = Vec::new();
+        for (idx, coords, elevation) in changes {
+            self.elevation[idx] = elevation;
+            touched.push(self.chunk_index(coords));
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        for chunk in touched {
+            self.chunks[chunk].upload_elevation(&self.elevation, self.dimensions.0);
+        }
+        Ok(())
+    }
+
+    /// The tile drawn at `coords` on `layer`, `Ok(None)` if the hex is empty
+    pub fn tile(
+        &self,
+        layer: &str,
+        coords: cgmath::Vector2<u32>,
+    ) -> Result<Option<(usize, Orientation)>, GridError> {
+        let layer = &self.layers[self.layer_index(layer)?];
+        let idx = self.checked_index(coords)?;
+        let tile = layer.contents[idx];
+        Ok((tile >= 0).then(|| (tile as usize, layer.orientations[idx])))
+    }
+
+    pub fn set_tile(
+        &mut self,
+        layer: &str,
+        coords: cgmath::Vector2<u32>,
+        tile: Option<usize>,
+        orientation: Orientation,
+    ) -> Result<(), GridError> {
+        self.set_tiles(layer, std::iter::once((coords, tile, orientation)))
+    }
+
+    /// Changes many tiles on `layer`, uploading each chunk touched once
+    ///
+    /// Nothing is changed if any of the hexes is off the grid.
+    pub fn set_tiles(
+        &mut self,
+        layer: &str,
+        tiles: impl IntoIterator<Item = (cgmath::Vector2<u32>, Option<usize>, Orientation)>,
+    ) -> Result<(), GridError> {
+        let layer = self.layer_index(layer)?;
+        let changes = tiles
+            .into_iter()
+            .map(|(coords, tile, orientation)| {
+                self.checked_index(coords)?;
+                let tile = tile.map(|x| x as isize).unwrap_or(-1);
+                Ok((coords, tile, orientation))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut touched = Vec::new();
+        for (coords, tile, orientation) in changes {
+            let idx = self.index(coords);
+            self.layers[layer].set(idx, tile, orientation);
+            touched.push(self.chunk_index(coords));
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        for chunk in touched {
+            self.chunks[chunk].upload_layer(layer, &self.layers[layer], self.dimensions.0);
+        }
+        Ok(())
+    }
+
+    /// Width and height of the grid, in hexes
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    /// Adds `count` empty rows or columns along `edge`
+    ///
+    /// Growing at the left or bottom moves every existing hex, so coordinates
+    /// held elsewhere (tokens, for one) need to be shifted by `count` as well.
+    pub fn grow(&mut self, edge: Edge, count: u32) -> Result<(), GridError> {
+        check_stagger(self.layout.shape, edge, count)?;
+        let (width, height) = self.dimensions;
+        let grown = |extent: u32| extent.checked_add(count).ok_or(GridError::TooLarge);
+        let (dimensions, shift) = match edge {
+            Edge::Left => ((grown(width)?, height), (count as i64, 0)),
+            Edge::Right => ((grown(width)?, height), (0, 0)),
+            Edge::Bottom => ((width, grown(height)?), (0, count as i64)),
+            Edge::Top => ((width, grown(height)?), (0, 0)),
+        };
+        // hexes are indexed with u32 arithmetic
+        if dimensions.0.checked_mul(dimensions.1).is_none() {
+            return Err(GridError::TooLarge);
+        }
+        self.reshape(dimensions, shift);
+        Ok(())
+    }
+
+    /// Removes `count` rows or columns along `edge`
+    ///
+    /// Shrinking at the left or bottom moves every remaining hex, see `grow`.
+    pub fn shrink(&mut self, edge: Edge, count: u32) -> Result<(), GridError> {
+        check_stagger(self.layout.shape, edge, count)?;
+        let (width, height) = self.dimensions;
+        let available = match edge {
+            Edge::Left | Edge::Right => width,
+            Edge::Bottom | Edge::Top => height,
+        };
+        if count >= available {
+            return Err(GridError::WouldBeEmpty);
+        }
+        let (dimensions, shift) = match edge {
+            Edge::Left => ((width - count, height), (-(count as i64), 0)),
+            Edge::Right => ((width - count, height), (0, 0)),
+            Edge::Bottom => ((width, height - count), (0, -(count as i64))),
+            Edge::Top => ((width, height - count), (0, 0)),
+        };
+        self.reshape(dimensions, shift);
+        Ok(())
+    }
+
+    /// Resizes to `dimensions`, moving the old hex at (x, y) to (x, y) + `shift`
+    fn reshape(&mut self, dimensions: (u32, u32), shift: (i64, i64)) {
+        let old = self.dimensions;
+        self.dimensions = dimensions;
+        for layer in &mut self.layers {
+            layer.contents = reshape_cells(&layer.contents, old, dimensions, shift, -1);
+            layer.orientations = reshape_cells(
+                &layer.orientations,
+                old,
+                dimensions,
+                shift,
+                Orientation::default(),
+            );
+        }
+        self.terrain = reshape_cells(&self.terrain, old, dimensions, shift, Terrain::default());
+        self.elevation = reshape_cells(&self.elevation, old, dimensions, shift, 0);
+        self.overlay.reshape(dimensions, shift);
+        self.rebuild_chunks();
+    }
+
+    /// Recreates every chunk's buffers from scratch
+    fn rebuild_chunks(&mut self) {
+        self.chunks.clear();
+        for (origin, size) in chunk::areas(self.dimensions) {
+            self.chunks.push(Chunk::new(
+                origin,
+                size,
+                self.layout,
+                &self.quad,
+                &self.elevation,
+                &self.layers,
+                self.dimensions.0,
+            ));
+        }
+        for chunk in 0..self.chunks.len() {
+            self.upload_tints(chunk);
+        }
+    }
+
+    fn chunk_index(&self, coords: cgmath::Vector2<u32>) -> usize {
+        let columns = self.dimensions.0.div_ceil(CHUNK_SIZE);
+        (coords.x / CHUNK_SIZE + columns * (coords.y / CHUNK_SIZE)) as usize
+    }
+
+    pub fn contains(&self, coords: cgmath::Vector2<u32>) -> bool {
+        coords.x < self.dimensions.0 && coords.y < self.dimensions.1
+    }
+
+    /// Index of `coords` into the per-hex data, which is stored row by row
+    fn index(&self, coords: cgmath::Vector2<u32>) -> usize {
+        (coords.x + self.dimensions.0 * coords.y) as usize
+    }
+
+    fn checked_index(&self, coords: cgmath::Vector2<u32>) -> Result<usize, GridError> {
+        if self.contains(coords) {
+            Ok(self.index(coords))
+        } else {
+            Err(GridError::OutOfBounds(coords))
+        }
+    }
+}
+
+/// Hex grids stagger every other row (or column), so changing how many
+/// there are below (or left of) the rest only works in pairs
+fn check_stagger(shape: Shape, edge: Edge, count: u32) -> Result<(), GridError> {
+    let staggered = matches!(
+        (shape, edge),
+        (Shape::Hex { point_up: true }, Edge::Bottom)
+            | (Shape::Hex { point_up: false }, Edge::Left)
+    );
+    if staggered && count % 2 == 1 {
+        Err(GridError::BreaksStagger)
+    } else {
+        Ok(())
+    }
+}
+
+fn reshape_cells<T: Clone>(
+    cells: &[T],
+    old: (u32, u32),
+    new: (u32, u32),
+    shift: (i64, i64),
+    fill: T,
+) -> Vec<T> {
+    let mut reshaped = Vec::with_capacity((new.0 * new.1) as usize);
+    for y in 0..new.1 as i64 {
+        for x in 0..new.0 as i64 {
+            let (old_x, old_y) = (x - shift.0, y - shift.1);
+            if (0..old.0 as i64).contains(&old_x) && (0..old.1 as i64).contains(&old_y) {
+                reshaped.push(cells[(old_x + old.0 as i64 * old_y) as usize].clone());
+            } else {
+                reshaped.push(fill.clone());
+            }
+        }
+    }
+    reshaped
+}
+
+/// A side of the grid, bottom being row 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GridError {
+    OutOfBounds(cgmath::Vector2<u32>),
+    NoSuchLayer(String),
+    LayerExists(String),
+    /// Adding or removing an odd number of staggered rows (or columns) would
+    /// change which ones are shifted
+    BreaksStagger,
+    /// Removing that many rows or columns would leave nothing
+    WouldBeEmpty,
+    /// Adding that many rows or columns would leave too many hexes to address
+    TooLarge,
+}
+
+impl std::fmt::Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GridError::OutOfBounds(coords) => {
+                write!(f, "({}, {}) is outside the grid", coords.x, coords.y)
+            }
+            GridError::NoSuchLayer(name) => write!(f, "there is no layer called {}", name),
+            GridError::LayerExists(name) => write!(f, "there already is a layer called {}", name),
+            GridError::BreaksStagger => {
+                write!(f, "staggered rows and columns can only change in pairs")
+            }
+            GridError::WouldBeEmpty => write!(f, "the grid can't be empty"),
+            GridError::TooLarge => write!(f, "the grid can't grow that large"),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINT_UP: Shape = Shape::Hex { point_up: true };
+    const FLAT_TOP: Shape = Shape::Hex { point_up: false };
+
+    /// A `width` by `height` grid whose cells hold their own coordinates
+    fn cells(width: u32, height: u32) -> Vec<Option<(u32, u32)>> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| Some((x, y))))
+            .collect()
+    }
+
+    #[test]
+    fn only_pairs_of_staggered_rows_and_columns_change() {
+        for &count in &[1, 2, 3, 4] {
+            let pairs = count % 2 == 0;
+            assert_eq!(check_stagger(POINT_UP, Edge::Bottom, count).is_ok(), pairs);
+            assert_eq!(check_stagger(FLAT_TOP, Edge::Left, count).is_ok(), pairs);
+            assert!(check_stagger(POINT_UP, Edge::Left, count).is_ok());
+            assert!(check_stagger(FLAT_TOP, Edge::Bottom, count).is_ok());
+            for &edge in &[Edge::Left, Edge::Right, Edge::Bottom, Edge::Top] {
+                assert!(check_stagger(Shape::Square, edge, count).is_ok());
+            }
+            for &shape in &[POINT_UP, FLAT_TOP] {
+                assert!(check_stagger(shape, Edge::Right, count).is_ok());
+                assert!(check_stagger(shape, Edge::Top, count).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn growing_on_the_left_or_bottom_moves_every_cell() {
+        let old = cells(3, 2);
+        let grown = reshape_cells(&old, (3, 2), (5, 2), (2, 0), None);
+        assert_eq!(grown.len(), 10);
+        assert_eq!(&grown[..2], &[None, None]);
+        assert_eq!(&grown[2..5], &old[..3]);
+        assert_eq!(grown[7..], old[3..]);
+
+        let grown = reshape_cells(&old, (3, 2), (3, 4), (0, 2), None);
+        assert_eq!(&grown[..6], &[None; 6]);
+        assert_eq!(grown[6..], old[..]);
+    }
+
+    #[test]
+    fn shrinking_on_the_left_or_bottom_drops_the_cells_there() {
+        let old = cells(4, 3);
+        let shrunk = reshape_cells(&old, (4, 3), (2, 3), (-2, 0), None);
+        let kept: Vec<_> = (0..3)
+            .flat_map(|y| vec![Some((2, y)), Some((3, y))])
+            .collect();
+        assert_eq!(shrunk, kept);
+
+        let shrunk = reshape_cells(&old, (4, 3), (4, 1), (0, -2), None);
+        assert_eq!(shrunk, old[8..]);
+    }
+
+    #[test]
+    fn allowed_changes_keep_the_stagger() {
+        // a cell keeps its stagger if it stays in a row (point-up) or column
+        // (flat-top) of the same parity
+        for &(shape, edge) in &[(POINT_UP, Edge::Bottom), (FLAT_TOP, Edge::Left)] {
+            for &count in &[1i64, 2] {
+                for &grow in &[true, false] {
+                    let allowed = check_stagger(shape, edge, count as u32).is_ok();
+                    assert_eq!(allowed, count == 2);
+                    if !allowed {
+                        continue;
+                    }
+                    let step = if grow { count } else { -count };
+                    let (shift, new) = match edge {
+                        Edge::Left => ((step, 0), ((4 + step) as u32, 4)),
+                        _ => ((0, step), (4, (4 + step) as u32)),
+                    };
+                    let reshaped = reshape_cells(&cells(4, 4), (4, 4), new, shift, None);
+                    for (idx, cell) in reshaped.iter().enumerate() {
+                        let (x, y) = (idx as u32 % new.0, idx as u32 / new.0);
+                        if let Some((old_x, old_y)) = cell {
+                            assert_eq!(old_x % 2, x % 2);
+                            assert_eq!(old_y % 2, y % 2);
+                        }
+                    }
+                }
+            }
+        }
     }
 }
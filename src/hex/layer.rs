@@ -0,0 +1,86 @@
+use super::atlas::TileAtlas;
+use super::tile::Tile;
+use crate::fgl;
+use serde::{Deserialize, Serialize};
+
+/// How a tile is turned on its hex
+///
+/// Tiles are mirrored left to right first, then rotated clockwise in steps
+/// of 60°, or 90° on square grids.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Orientation {
+    pub rotation: u8,
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    pub fn rotated(rotation: u8) -> Self {
+        Self {
+            rotation,
+            mirrored: false,
+        }
+    }
+
+    /// Packed into one float for the grid shader
+    fn code(self) -> f32 {
+        // 12 steps is a full turn on both hex and square grids
+        ((self.rotation % 12) + if self.mirrored { 16 } else { 0 }) as f32
+    }
+}
+
+/// One tileset's worth of tiles, drawn over the layers below it
+pub struct TileLayer {
+    name: String,
+    atlas: TileAtlas,
+    /// Tile index per hex, row by row, -1 where the layer is empty
+    pub(super) contents: Vec<isize>,
+    pub(super) orientations: Vec<Orientation>,
+    pub visible: bool,
+    pub opacity: f32,
+    /// Only for the GM's eyes, left out of player facing renders like exports
+    pub gm_only: bool,
+}
+
+impl TileLayer {
+    pub(super) fn new(
+        name: &str,
+        tiles: &[Tile],
+        contents: Vec<isize>,
+        orientations: Vec<Orientation>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            atlas: TileAtlas::new(tiles),
+            contents,
+            orientations,
+            visible: true,
+            opacity: 1.0,
+            gm_only: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether any of the layer's tiles are animated
+    pub fn animated(&self) -> bool {
+        self.atlas.animated()
+    }
+
+    /// Tile index and orientation code of the hex at `idx`, as the grid shader takes them
+    pub(super) fn instance(&self, idx: usize) -> [f32; 2] {
+        [self.contents[idx] as f32, self.orientations[idx].code()]
+    }
+
+    pub(super) fn set(&mut self, idx: usize, tile: isize, orientation: Orientation) {
+        self.contents[idx] = tile;
+        self.orientations[idx] = orientation;
+    }
+
+    /// Binds the tileset and sets the per-layer uniforms of the grid program
+    pub(super) fn bind(&self, program: &fgl::Program) {
+        self.atlas.bind(program);
+        program.uniform_f32("opacity", self.opacity);
+    }
+}
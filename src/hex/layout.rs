@@ -0,0 +1,432 @@
+use super::coord::Hex;
+use super::token::CentredOn;
+use cgmath::Vector2;
+use serde::{Deserialize, Serialize};
+
+/// Gridless maps still place things on a fine square lattice, with this many
+/// points per tile
+pub const GRIDLESS_SUBDIVISIONS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Shape {
+    Hex { point_up: bool },
+    Square,
+    Gridless,
+}
+
+/// How cell coordinates map onto the world
+///
+/// Hex geometry (line of sight, cover and area templates) is only
+/// meaningful for hex layouts, everything else works on any shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub shape: Shape,
+    pub tile_size: f32,
+    /// World position the grid is moved to, so it can line up with a background image
+    pub origin: Vector2<f32>,
+}
+
+impl Layout {
+    pub fn new(shape: Shape, tile_size: f32) -> Self {
+        Self {
+            shape,
+            tile_size,
+            origin: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn with_origin(self, origin: Vector2<f32>) -> Self {
+        Self { origin, ..self }
+    }
+
+    /// Width and height of a single cell
+    pub fn cell_size(&self) -> f32 {
+        match self.shape {
+            Shape::Gridless => self.tile_size / GRIDLESS_SUBDIVISIONS as f32,
+            _ => self.tile_size,
+        }
+    }
+
+    /// World position of the centre of the cell at `coords`
+    pub fn cell_to_world(&self, coords: Vector2<u32>) -> Vector2<f32> {
+        match self.shape {
+            Shape::Hex { point_up } => {
+                self.origin + super::grid_to_world(coords, self.tile_size, point_up)
+            }
+            _ => self.tile_origin(coords) + Vector2::new(1.0, 1.0) * self.cell_size() / 2.0,
+        }
+    }
+
+    /// Cell containing the world position
+    pub fn world_to_cell(&self, world: Vector2<f32>) -> Option<Vector2<u32>> {
+        let world = world - self.origin;
+        match self.shape {
+            Shape::Hex { point_up } => super::world_to_grid(world, self.tile_size, point_up),
+            _ => {
+                let cell = world / self.cell_size();
+                if cell.x < 0.0 || cell.y < 0.0 {
+                    None
+                } else {
+                    Some(cell.map(|x| x as u32))
+                }
+            }
+        }
+    }
+
+    /// Bottom left corner of the quad the cell at `coords` is drawn into
+    pub fn tile_origin(&self, coords: Vector2<u32>) -> Vector2<f32> {
+        match self.shape {
+            Shape::Hex { point_up } => {
+                self.origin + super::tile_origin(coords, self.tile_size, point_up)
+            }
+            _ => self.origin + coords.map(|x| x as f32) * self.cell_size(),
+        }
+    }
+
+    /// Offset from a cell's centre of the corner tokens centred on a corner use
+    ///
+    /// `upper` picks the top (or upper right) corner rather than the opposite one.
+    pub fn corner_offset(&self, upper: bool) -> Vector2<f32> {
+        match self.shape {
+            Shape::Hex { point_up } => {
+                super::corner_offset(self.tile_size, if upper { 0 } else { 3 }, point_up)
+            }
+            Shape::Square if upper => Vector2::new(1.0, 1.0) * self.tile_size / 2.0,
+            Shape::Square => Vector2::new(-1.0, -1.0) * self.tile_size / 2.0,
+            Shape::Gridless => Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /// Corners of the cell at `coords` in world space, going clockwise
+    ///
+    /// Gridless cells have no outline, so they have no corners either.
+    pub fn corners(&self, coords: Vector2<u32>) -> Vec<Vector2<f32>> {
+        let centre = self.cell_to_world(coords);
+        match self.shape {
+            Shape::Hex { point_up } => (0..6)
+                .map(|corner| centre + super::corner_offset(self.tile_size, corner, point_up))
+                .collect(),
+            Shape::Square => {
+                IntoIterator::into_iter([(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)])
+                    .map(|(x, y)| centre + Vector2::new(x, y) * self.tile_size / 2.0)
+                    .collect()
+            }
+            Shape::Gridless => Vec::new(),
+        }
+    }
+
+    /// Cells adjacent to `coords`, diagonals included on square lattices
+    pub fn neighbours(&self, coords: Vector2<u32>) -> Vec<Vector2<u32>> {
+        match self.shape {
+            Shape::Hex { .. } => IntoIterator::into_iter(self.hex(coords).neighbours())
+                .filter_map(|hex| self.offset(hex))
+                .collect(),
+            _ => {
+                let mut neighbours = Vec::with_capacity(8);
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        if (dx, dy) != (0, 0) {
+                            neighbours.extend(offset_by(coords, dx, dy));
+                        }
+                    }
+                }
+                neighbours
+            }
+        }
+    }
+
+    /// Number of steps between two cells
+    pub fn distance(&self, a: Vector2<u32>, b: Vector2<u32>) -> u32 {
+        match self.shape {
+            Shape::Hex { .. } => self.hex(a).distance(self.hex(b)),
+            _ => u32::max(a.x.abs_diff(b.x), a.y.abs_diff(b.y)),
+        }
+    }
+
+    /// Cells covered by a token of `size` placed at `anchor`
+    ///
+    /// Odd sizes are centred on a cell and even sizes on a corner, so tokens
+    /// should be centred accordingly. Sizes that don't match how the token is
+    /// centred round down, except that tokens smaller than 2 always cover
+    /// exactly one cell. On gridless maps tokens cover a disc instead. Cells
+    /// past the left or bottom edge are left out.
+    pub fn footprint(
+        &self,
+        anchor: Vector2<u32>,
+        size: u32,
+        centred_on: CentredOn,
+    ) -> Vec<Vector2<u32>> {
+        self.unclipped_footprint(anchor, size, centred_on)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// The footprint as for `footprint`, `None` if any of it is outside a grid
+    /// of `dimensions`
+    pub fn footprint_within(
+        &self,
+        anchor: Vector2<u32>,
+        size: u32,
+        centred_on: CentredOn,
+        dimensions: (u32, u32),
+    ) -> Option<Vec<Vector2<u32>>> {
+        self.unclipped_footprint(anchor, size, centred_on)
+            .into_iter()
+            .map(|coords| coords.filter(|c| c.x < dimensions.0 && c.y < dimensions.1))
+            .collect()
+    }
+
+    /// Every cell of the footprint, `None` for those past the left or bottom edge
+    fn unclipped_footprint(
+        &self,
+        anchor: Vector2<u32>,
+        size: u32,
+        centred_on: CentredOn,
+    ) -> Vec<Option<Vector2<u32>>> {
+        match (self.shape, centred_on) {
+            (Shape::Gridless, _) => {
+                let radius = (size.max(1) * GRIDLESS_SUBDIVISIONS / 2) as i64;
+                let mut footprint = Vec::new();
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        if dx * dx + dy * dy <= radius * radius {
+                            footprint.push(offset_by(anchor, dx, dy));
+                        }
+                    }
+                }
+                footprint
+            }
+            (Shape::Hex { .. }, CentredOn::Tile) => self
+                .hex(anchor)
+                .spiral(size.saturating_sub(1) / 2)
+                .into_iter()
+                .map(|hex| self.offset(hex))
+                .collect(),
+            (Shape::Hex { point_up }, CentredOn::Corner { point_up: upper }) => {
+                if size < 2 {
+                    return vec![Some(anchor)];
+                }
+                let hex = self.hex(anchor);
+                let [a, b] = super::corner_directions(if upper { 0 } else { 3 }, point_up);
+                let mut footprint: Vec<_> = [hex, hex.neighbour(a), hex.neighbour(b)]
+                    .iter()
+                    .flat_map(|hex| hex.spiral(size / 2 - 1))
+                    .collect();
+                footprint.sort();
+                footprint.dedup();
+                footprint.into_iter().map(|hex| self.offset(hex)).collect()
+            }
+            (Shape::Square, CentredOn::Tile) => {
+                let k = (size.saturating_sub(1) / 2) as i64;
+                square_block(anchor, -k..=k)
+            }
+            (Shape::Square, CentredOn::Corner { point_up: upper }) => {
+                if size < 2 {
+                    return vec![Some(anchor)];
+                }
+                let k = (size / 2) as i64;
+                square_block(anchor, if upper { 1 - k..=k } else { -k..=k - 1 })
+            }
+        }
+    }
+
+    /// Axial coordinates of the cell at `coords`
+    ///
+    /// Only meaningful for hex layouts, other shapes are treated as point-up.
+    pub fn hex(&self, coords: Vector2<u32>) -> Hex {
+        Hex::from_offset(coords, self.point_up())
+    }
+
+    /// Offset coordinates of `hex`, if it has any
+    ///
+    /// Only meaningful for hex layouts, other shapes are treated as point-up.
+    pub fn offset(&self, hex: Hex) -> Option<Vector2<u32>> {
+        hex.to_offset(self.point_up())
+    }
+
+    fn point_up(&self) -> bool {
+        match self.shape {
+            Shape::Hex { point_up } => point_up,
+            _ => true,
+        }
+    }
+
+    /// The `shape` uniform the grid shaders clip cells with
+    pub fn shader_shape(&self) -> i32 {
+        match self.shape {
+            Shape::Square | Shape::Gridless => 0,
+            Shape::Hex { point_up: true } => 1,
+            Shape::Hex { point_up: false } => 2,
+        }
+    }
+}
+
+fn offset_by(coords: Vector2<u32>, dx: i64, dy: i64) -> Option<Vector2<u32>> {
+    let (x, y) = (coords.x as i64 + dx, coords.y as i64 + dy);
+    if x < 0 || y < 0 {
+        None
+    } else {
+        Some(Vector2::new(x as u32, y as u32))
+    }
+}
+
+fn square_block(
+    anchor: Vector2<u32>,
+    range: std::ops::RangeInclusive<i64>,
+) -> Vec<Option<Vector2<u32>>> {
+    let mut block = Vec::new();
+    for dy in range.clone() {
+        for dx in range.clone() {
+            block.push(offset_by(anchor, dx, dy));
+        }
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANCHOR: Vector2<u32> = Vector2::new(10, 10);
+
+    fn hex_layout(point_up: bool) -> Layout {
+        Layout::new(Shape::Hex { point_up }, 64.0)
+    }
+
+    #[test]
+    fn small_tokens_cover_one_cell() {
+        for layout in &[
+            hex_layout(true),
+            hex_layout(false),
+            Layout::new(Shape::Square, 64.0),
+        ] {
+            for &centred_on in &[
+                CentredOn::Tile,
+                CentredOn::Corner { point_up: true },
+                CentredOn::Corner { point_up: false },
+            ] {
+                assert_eq!(layout.footprint(ANCHOR, 1, centred_on), vec![ANCHOR]);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_tokens_centred_on_a_tile_cover_rings_around_it() {
+        for &point_up in &[true, false] {
+            let layout = hex_layout(point_up);
+            for &(size, cells) in &[(3, 7), (5, 19)] {
+                let footprint = layout.footprint(ANCHOR, size, CentredOn::Tile);
+                assert_eq!(footprint.len(), cells);
+                assert!(footprint
+                    .iter()
+                    .all(|coords| layout.distance(ANCHOR, *coords) <= size / 2));
+            }
+        }
+    }
+
+    #[test]
+    fn hex_tokens_centred_on_a_corner_cover_the_cells_around_it() {
+        for &point_up in &[true, false] {
+            let layout = hex_layout(point_up);
+            for &upper in &[true, false] {
+                let footprint = layout.footprint(ANCHOR, 2, CentredOn::Corner { point_up: upper });
+                assert_eq!(footprint.len(), 3);
+                assert!(footprint.contains(&ANCHOR));
+                for a in &footprint {
+                    for b in &footprint {
+                        assert!(layout.distance(*a, *b) <= 1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn square_tokens_cover_blocks() {
+        let layout = Layout::new(Shape::Square, 64.0);
+        assert_eq!(layout.footprint(ANCHOR, 3, CentredOn::Tile).len(), 9);
+        let upper = layout.footprint(ANCHOR, 2, CentredOn::Corner { point_up: true });
+        assert_eq!(
+            upper,
+            vec![
+                ANCHOR,
+                ANCHOR + Vector2::new(1, 0),
+                ANCHOR + Vector2::new(0, 1),
+                ANCHOR + Vector2::new(1, 1),
+            ]
+        );
+        let lower = layout.footprint(ANCHOR, 2, CentredOn::Corner { point_up: false });
+        assert_eq!(lower.len(), 4);
+        assert!(lower.contains(&(ANCHOR - Vector2::new(1, 1))));
+    }
+
+    #[test]
+    fn gridless_tokens_cover_a_disc() {
+        let layout = Layout::new(Shape::Gridless, 64.0);
+        let radius = (GRIDLESS_SUBDIVISIONS / 2) as i64;
+        let centre = ANCHOR * GRIDLESS_SUBDIVISIONS;
+        let footprint = layout.footprint(centre, 1, CentredOn::Tile);
+        assert!(footprint.contains(&centre));
+        for coords in footprint {
+            let d = coords.cast::<i64>().unwrap() - centre.cast().unwrap();
+            assert!(d.x * d.x + d.y * d.y <= radius * radius);
+        }
+    }
+
+    #[test]
+    fn footprints_are_clipped_at_the_grid_edge() {
+        let footprint = hex_layout(true).footprint(Vector2::new(0, 0), 3, CentredOn::Tile);
+        assert!(footprint.len() < 7);
+        assert!(footprint.contains(&Vector2::new(0, 0)));
+    }
+
+    const GRID: (u32, u32) = (20, 20);
+
+    /// Whether a token of size 3 fits on a `GRID` sized grid at `anchor`, on every shape
+    fn fits(anchor: Vector2<u32>) -> bool {
+        let fits: Vec<_> = [
+            hex_layout(true),
+            hex_layout(false),
+            Layout::new(Shape::Square, 64.0),
+        ]
+        .iter()
+        .map(|layout| {
+            layout
+                .footprint_within(anchor, 3, CentredOn::Tile, GRID)
+                .is_some()
+        })
+        .collect();
+        assert!(fits.iter().all(|&fit| fit == fits[0]), "{:?}", fits);
+        fits[0]
+    }
+
+    #[test]
+    fn footprints_fit_inside_the_grid() {
+        assert!(fits(ANCHOR));
+        assert_eq!(
+            hex_layout(true).footprint_within(ANCHOR, 3, CentredOn::Tile, GRID),
+            Some(hex_layout(true).footprint(ANCHOR, 3, CentredOn::Tile))
+        );
+    }
+
+    #[test]
+    fn footprints_over_the_left_edge_dont_fit() {
+        assert!(!fits(Vector2::new(0, ANCHOR.y)));
+    }
+
+    #[test]
+    fn footprints_over_the_bottom_edge_dont_fit() {
+        assert!(!fits(Vector2::new(ANCHOR.x, 0)));
+    }
+
+    #[test]
+    fn footprints_over_the_right_edge_dont_fit() {
+        assert!(!fits(Vector2::new(GRID.0 - 1, ANCHOR.y)));
+    }
+
+    #[test]
+    fn footprints_over_the_top_edge_dont_fit() {
+        assert!(!fits(Vector2::new(ANCHOR.x, GRID.1 - 1)));
+    }
+}
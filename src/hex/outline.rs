@@ -0,0 +1,198 @@
+use super::chunk;
+use super::grid::HexGrid;
+use super::layout::{Layout, Shape};
+use crate::render::line::LineRenderer;
+use crate::render::text::TextRenderer;
+use cgmath::{Matrix4, Vector2, Vector4};
+use std::collections::HashSet;
+
+/// How cells are labelled with their coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelFormat {
+    /// Column then row, zero padded and counted from 1 at the top left, like "0312"
+    Hexcrawl,
+    /// The offset coordinates as they are, like "2,11"
+    Offset,
+}
+
+impl LabelFormat {
+    /// Label of the cell at `coords` on a grid of `dimensions`
+    pub fn label(self, coords: Vector2<u32>, dimensions: (u32, u32)) -> String {
+        match self {
+            LabelFormat::Hexcrawl => {
+                let digits = u32::max(dimensions.0, dimensions.1)
+                    .to_string()
+                    .len()
+                    .max(2);
+                format!(
+                    "{:0width$}{:0width$}",
+                    coords.x + 1,
+                    dimensions.1 - coords.y,
+                    width = digits
+                )
+            }
+            LabelFormat::Offset => format!("{},{}", coords.x, coords.y),
+        }
+    }
+}
+
+/// Colours and sizes for `GridOutline`, widths and heights are fractions of the tile size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutlineStyle {
+    pub colour: Vector4<f32>,
+    pub width: f32,
+    pub highlight: Vector4<f32>,
+    pub highlight_width: f32,
+    pub labels: Option<LabelFormat>,
+    pub label_colour: Vector4<f32>,
+    pub label_height: f32,
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        Self {
+            colour: Vector4::new(0.1, 0.1, 0.1, 0.6),
+            width: 0.02,
+            highlight: Vector4::new(1.0, 1.0, 1.0, 0.9),
+            highlight_width: 0.05,
+            labels: Some(LabelFormat::Hexcrawl),
+            label_colour: Vector4::new(0.1, 0.1, 0.1, 0.8),
+            label_height: 0.15,
+        }
+    }
+}
+
+/// Cell borders, coordinate labels and a highlight on the hovered cell,
+/// drawn on top of the grid's tiles
+///
+/// Borders and labels are only built for the chunks of the grid in view.
+pub struct GridOutline {
+    style: OutlineStyle,
+    layout: Layout,
+    dimensions: (u32, u32),
+    hovered: Option<Vector2<u32>>,
+    /// Origins of the chunks the borders and labels were built for, `None`
+    /// once they need building again
+    built: Option<Vec<Vector2<u32>>>,
+    lines: LineRenderer,
+    highlight: LineRenderer,
+    text: TextRenderer,
+    /// Hides the whole outline, labels and highlight included
+    pub visible: bool,
+    pub labels_visible: bool,
+}
+
+impl GridOutline {
+    pub fn new(grid: &HexGrid, style: OutlineStyle) -> Result<Self, String> {
+        let mut outline = Self {
+            style,
+            layout: grid.layout(),
+            dimensions: grid.dimensions(),
+            hovered: None,
+            built: None,
+            lines: LineRenderer::new()?,
+            highlight: LineRenderer::new()?,
+            text: TextRenderer::new(48.0)?,
+            visible: true,
+            labels_visible: true,
+        };
+        outline.rebuild(grid);
+        Ok(outline)
+    }
+
+    pub fn style(&self) -> OutlineStyle {
+        self.style
+    }
+
+    pub fn set_style(&mut self, grid: &HexGrid, style: OutlineStyle) {
+        self.style = style;
+        self.rebuild(grid);
+    }
+
+    /// Picks up the grid's layout and size, needed whenever the grid is resized
+    pub fn rebuild(&mut self, grid: &HexGrid) {
+        self.layout = grid.layout();
+        self.dimensions = grid.dimensions();
+        self.built = None;
+        self.hover(self.hovered);
+    }
+
+    /// Chunks that might be on screen when drawing with `projection`
+    fn visible_chunks(&self, projection: Matrix4<f32>) -> Vec<(Vector2<u32>, Vector2<u32>)> {
+        let view = match chunk::view_bounds(projection) {
+            Some(view) if self.layout.shape != Shape::Gridless => view,
+            _ => return Vec::new(),
+        };
+        chunk::areas(self.dimensions)
+            .into_iter()
+            .filter(|(origin, size)| {
+                chunk::overlaps(chunk::bounds(self.layout, *origin, *size), view)
+            })
+            .collect()
+    }
+
+    /// Regenerates the borders and labels of the cells in `chunks`
+    fn build(&mut self, chunks: &[(Vector2<u32>, Vector2<u32>)]) {
+        self.lines.clear();
+        self.text.clear();
+        let size = self.layout.tile_size;
+        let width = self.style.width * size;
+        let height = self.style.label_height * size;
+        // neighbours share edges, so only draw each once to keep the alpha even
+        let mut drawn = HashSet::new();
+        for (origin, extent) in chunks {
+            for y in 0..extent.y {
+                for x in 0..extent.x {
+                    let coords = origin + Vector2::new(x, y);
+                    let corners = self.layout.corners(coords);
+                    for (n, a) in corners.iter().enumerate() {
+                        let b = corners[(n + 1) % corners.len()];
+                        let mid = (a + b) / 2.0 * 100.0 / size;
+                        if drawn.insert((mid.x.round() as i64, mid.y.round() as i64)) {
+                            self.lines.segment(*a, b, width, self.style.colour);
+                        }
+                    }
+                    if let Some(format) = self.style.labels {
+                        let at = self.layout.cell_to_world(coords) + Vector2::new(0.0, size * 0.3);
+                        let label = format.label(coords, self.dimensions);
+                        self.text.label(&label, at, height, self.style.label_colour);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the highlight to `coords`, or removes it
+    pub fn hover(&mut self, coords: Option<Vector2<u32>>) {
+        self.hovered = coords;
+        self.highlight.clear();
+        if let Some(coords) = coords {
+            let width = self.style.highlight_width * self.layout.tile_size;
+            let corners = self.layout.corners(coords);
+            for (n, a) in corners.iter().enumerate() {
+                let b = corners[(n + 1) % corners.len()];
+                self.highlight.segment(*a, b, width, self.style.highlight);
+            }
+        }
+    }
+
+    pub fn draw(&mut self, projection: Matrix4<f32>) {
+        if !self.visible {
+            return;
+        }
+        let chunks = self.visible_chunks(projection);
+        let origins: Vec<_> = chunks.iter().map(|(origin, _)| *origin).collect();
+        if self.built.as_ref() != Some(&origins) {
+            self.build(&chunks);
+            self.built = Some(origins);
+        }
+        self.lines.update();
+        self.lines.draw(projection);
+        if self.labels_visible {
+            self.text.update();
+            self.text.draw(projection);
+        }
+        self.highlight.update();
+        self.highlight.draw(projection);
+    }
+}
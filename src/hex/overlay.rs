@@ -0,0 +1,172 @@
+use super::coord::Hex;
+use super::layout::Layout;
+use cgmath::{Vector2, Vector4};
+use std::collections::HashMap;
+
+/// One named set of tinted hexes, cleared independently of the others
+pub struct OverlayChannel {
+    name: String,
+    layout: Layout,
+    tints: HashMap<Vector2<u32>, Vector4<f32>>,
+    /// Hexes changed since the grid last uploaded them
+    changed: Vec<Vector2<u32>>,
+}
+
+impl OverlayChannel {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tint(&mut self, coords: Vector2<u32>, colour: Vector4<f32>) {
+        self.tints.insert(coords, colour);
+        self.changed.push(coords);
+    }
+
+    /// Tints `hex`, if it has offset coordinates
+    pub fn tint_hex(&mut self, hex: Hex, colour: Vector4<f32>) {
+        if let Some(coords) = self.layout.offset(hex) {
+            self.tint(coords, colour);
+        }
+    }
+
+    pub fn tint_all(
+        &mut self,
+        hexes: impl IntoIterator<Item = Vector2<u32>>,
+        colour: Vector4<f32>,
+    ) {
+        for coords in hexes {
+            self.tint(coords, colour);
+        }
+    }
+
+    pub fn clear_tint(&mut self, coords: Vector2<u32>) {
+        if self.tints.remove(&coords).is_some() {
+            self.changed.push(coords);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.changed
+            .extend(self.tints.drain().map(|(coords, _)| coords));
+    }
+
+    pub fn tint_at(&self, coords: Vector2<u32>) -> Option<Vector4<f32>> {
+        self.tints.get(&coords).copied()
+    }
+}
+
+/// Translucent tints drawn over individual hexes, sorted into channels
+///
+/// Channels are stacked in the order they were first used, later ones
+/// blending over earlier ones where they overlap.
+pub struct HexOverlay {
+    layout: Layout,
+    channels: Vec<OverlayChannel>,
+    /// Hexes of channels removed since the grid last uploaded them
+    removed: Vec<Vector2<u32>>,
+}
+
+impl HexOverlay {
+    pub(super) fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            channels: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    /// The channel called `name`, created empty on top of the others if there is none yet
+    pub fn channel(&mut self, name: &str) -> &mut OverlayChannel {
+        let idx = match self
+            .channels
+            .iter()
+            .position(|channel| channel.name == name)
+        {
+            Some(idx) => idx,
+            None => {
+                self.channels.push(OverlayChannel {
+                    name: name.to_string(),
+                    layout: self.layout,
+                    tints: HashMap::new(),
+                    changed: Vec::new(),
+                });
+                self.channels.len() - 1
+            }
+        };
+        &mut self.channels[idx]
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &OverlayChannel> {
+        self.channels.iter()
+    }
+
+    pub fn remove_channel(&mut self, name: &str) {
+        if let Some(idx) = self
+            .channels
+            .iter()
+            .position(|channel| channel.name == name)
+        {
+            let channel = self.channels.remove(idx);
+            self.removed.extend(channel.tints.keys().copied());
+            self.removed.extend(channel.changed);
+        }
+    }
+
+    /// Clears every channel
+    pub fn clear(&mut self) {
+        for channel in &mut self.channels {
+            channel.clear();
+        }
+    }
+
+    pub(super) fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        for channel in &mut self.channels {
+            channel.layout = layout;
+        }
+    }
+
+    /// Every channel's tint at `coords` blended together, transparent if there are none
+    pub(super) fn composite(&self, coords: Vector2<u32>) -> Vector4<f32> {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.tint_at(coords))
+            .fold(Vector4::new(0.0, 0.0, 0.0, 0.0), |below, above| {
+                let alpha = above.w + below.w * (1.0 - above.w);
+                if alpha <= 0.0 {
+                    return Vector4::new(0.0, 0.0, 0.0, 0.0);
+                }
+                let colour = (above.truncate() * above.w
+                    + below.truncate() * below.w * (1.0 - above.w))
+                    / alpha;
+                colour.extend(alpha)
+            })
+    }
+
+    /// Hexes changed since the last call, possibly with repeats
+    pub(super) fn take_changed(&mut self) -> Vec<Vector2<u32>> {
+        let mut changed = std::mem::take(&mut self.removed);
+        for channel in &mut self.channels {
+            changed.append(&mut channel.changed);
+        }
+        changed
+    }
+
+    /// Moves every tint along with the grid being resized, see `HexGrid::grow`
+    pub(super) fn reshape(&mut self, dimensions: (u32, u32), shift: (i64, i64)) {
+        self.removed.clear();
+        for channel in &mut self.channels {
+            channel.changed.clear();
+            channel.tints = channel
+                .tints
+                .drain()
+                .filter_map(|(coords, tint)| {
+                    let (x, y) = (coords.x as i64 + shift.0, coords.y as i64 + shift.1);
+                    let inside = (0..dimensions.0 as i64).contains(&x)
+                        && (0..dimensions.1 as i64).contains(&y);
+                    inside.then(|| (Vector2::new(x as u32, y as u32), tint))
+                })
+                .collect();
+        }
+    }
+}
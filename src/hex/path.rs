@@ -0,0 +1,349 @@
+use super::grid::HexGrid;
+use super::layout::Layout;
+use super::overlay::OverlayChannel;
+use super::token::CentredOn;
+use cgmath::{Vector2, Vector4};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const PATH: Vector4<f32> = Vector4::new(0.9, 0.8, 0.1, 0.5);
+const REACHABLE: Vector4<f32> = Vector4::new(0.1, 0.4, 0.9, 0.35);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    /// Hexes from the start to the goal, both included
+    pub hexes: Vec<Vector2<u32>>,
+    pub cost: u32,
+}
+
+impl Path {
+    pub fn show(&self, overlay: &mut OverlayChannel) {
+        for coords in &self.hexes {
+            overlay.tint(*coords, PATH);
+        }
+    }
+}
+
+/// Every hex reachable within a movement budget
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MovementRange {
+    /// Cheapest cost to reach each hex, including the start at 0
+    pub costs: HashMap<Vector2<u32>, u32>,
+    came_from: HashMap<Vector2<u32>, Vector2<u32>>,
+}
+
+impl MovementRange {
+    pub fn contains(&self, coords: Vector2<u32>) -> bool {
+        self.costs.contains_key(&coords)
+    }
+
+    /// The cheapest path to a hex in range
+    pub fn path_to(&self, coords: Vector2<u32>) -> Option<Path> {
+        let cost = *self.costs.get(&coords)?;
+        Some(Path {
+            hexes: walk_back(&self.came_from, coords),
+            cost,
+        })
+    }
+
+    pub fn show(&self, overlay: &mut OverlayChannel) {
+        for coords in self.costs.keys() {
+            overlay.tint(*coords, REACHABLE);
+        }
+    }
+}
+
+impl HexGrid {
+    /// Cheapest path between two hexes for a token's anchor, if there is one
+    ///
+    /// Every cell the token covers at each step has to be on the grid,
+    /// passable and not in `occupied`, though cells the token covers at the
+    /// start may be occupied.
+    pub fn find_path(
+        &self,
+        from: Vector2<u32>,
+        to: Vector2<u32>,
+        size: u32,
+        centred_on: CentredOn,
+        occupied: &HashSet<Vector2<u32>>,
+    ) -> Option<Path> {
+        let occupied = vacate(occupied, &self.layout().footprint(from, size, centred_on));
+        search_path(self.layout(), from, to, |anchor| {
+            self.step_cost(anchor, size, centred_on, &occupied)
+        })
+    }
+
+    /// All hexes a token's anchor can reach from `from` spending at most `budget`
+    ///
+    /// Cells are passable the same way as for `find_path`.
+    pub fn movement_range(
+        &self,
+        from: Vector2<u32>,
+        budget: u32,
+        size: u32,
+        centred_on: CentredOn,
+        occupied: &HashSet<Vector2<u32>>,
+    ) -> MovementRange {
+        let occupied = vacate(occupied, &self.layout().footprint(from, size, centred_on));
+        search_range(self.layout(), from, budget, |anchor| {
+            self.step_cost(anchor, size, centred_on, &occupied)
+        })
+    }
+
+    /// What moving a token's anchor onto `anchor` costs
+    fn step_cost(
+        &self,
+        anchor: Vector2<u32>,
+        size: u32,
+        centred_on: CentredOn,
+        occupied: &HashSet<Vector2<u32>>,
+    ) -> Option<u32> {
+        let footprint =
+            self.layout()
+                .footprint_within(anchor, size, centred_on, self.dimensions())?;
+        footprint_cost(&footprint, occupied, |coords| {
+            self.terrain(coords)?.movement_cost
+        })
+    }
+}
+
+/// `occupied` without the cells a token covers where it starts, which it
+/// occupies itself
+fn vacate(occupied: &HashSet<Vector2<u32>>, start: &[Vector2<u32>]) -> HashSet<Vector2<u32>> {
+    occupied
+        .iter()
+        .filter(|coords| !start.contains(coords))
+        .copied()
+        .collect()
+}
+
+/// What covering every cell of `footprint` costs, the most expensive one
+/// deciding, or `None` if any of them is occupied or can't be entered
+fn footprint_cost(
+    footprint: &[Vector2<u32>],
+    occupied: &HashSet<Vector2<u32>>,
+    cell_cost: impl Fn(Vector2<u32>) -> Option<u32>,
+) -> Option<u32> {
+    footprint.iter().try_fold(1, |worst, coords| {
+        if occupied.contains(coords) {
+            return None;
+        }
+        // free moves would make the distance heuristic an overestimate
+        Some(worst.max(cell_cost(*coords)?))
+    })
+}
+
+/// A* from `from` to `to`, `cost` being what entering a cell costs, `None`
+/// if it can't be entered
+fn search_path(
+    layout: Layout,
+    from: Vector2<u32>,
+    to: Vector2<u32>,
+    cost: impl Fn(Vector2<u32>) -> Option<u32>,
+) -> Option<Path> {
+    let mut costs = HashMap::new();
+    let mut came_from = HashMap::new();
+    let mut frontier = BinaryHeap::new();
+    costs.insert(from, 0);
+    frontier.push((Reverse(layout.distance(from, to)), Reverse(0), Key(from)));
+
+    while let Some((_, Reverse(spent), Key(current))) = frontier.pop() {
+        if current == to {
+            return Some(Path {
+                hexes: walk_back(&came_from, to),
+                cost: spent,
+            });
+        }
+        if spent > costs[&current] {
+            continue;
+        }
+        for next in layout.neighbours(current) {
+            let next_cost = match cost(next) {
+                Some(step) => spent + step,
+                None => continue,
+            };
+            if costs.get(&next).is_none_or(|&old| next_cost < old) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, current);
+                let estimate = next_cost + layout.distance(next, to);
+                frontier.push((Reverse(estimate), Reverse(next_cost), Key(next)));
+            }
+        }
+    }
+    None
+}
+
+/// Dijkstra from `from` up to `budget`, with `cost` as for `search_path`
+fn search_range(
+    layout: Layout,
+    from: Vector2<u32>,
+    budget: u32,
+    cost: impl Fn(Vector2<u32>) -> Option<u32>,
+) -> MovementRange {
+    let mut range = MovementRange::default();
+    let mut frontier = BinaryHeap::new();
+    range.costs.insert(from, 0);
+    frontier.push((Reverse(0), Key(from)));
+
+    while let Some((Reverse(spent), Key(current))) = frontier.pop() {
+        if spent > range.costs[&current] {
+            continue;
+        }
+        for next in layout.neighbours(current) {
+            let next_cost = match cost(next) {
+                Some(step) => spent + step,
+                None => continue,
+            };
+            if next_cost <= budget && range.costs.get(&next).is_none_or(|&old| next_cost < old) {
+                range.costs.insert(next, next_cost);
+                range.came_from.insert(next, current);
+                frontier.push((Reverse(next_cost), Key(next)));
+            }
+        }
+    }
+    range
+}
+
+/// `Vector2` isn't `Ord`, which `BinaryHeap` needs to break ties
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Key(Vector2<u32>);
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.0.x, self.0.y).cmp(&(other.0.x, other.0.y))
+    }
+}
+
+fn walk_back(
+    came_from: &HashMap<Vector2<u32>, Vector2<u32>>,
+    to: Vector2<u32>,
+) -> Vec<Vector2<u32>> {
+    let mut hexes = vec![to];
+    let mut current = to;
+    while let Some(previous) = came_from.get(&current) {
+        hexes.push(*previous);
+        current = *previous;
+    }
+    hexes.reverse();
+    hexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex::layout::Shape;
+
+    const SIZE: u32 = 10;
+
+    fn layout() -> Layout {
+        Layout::new(Shape::Hex { point_up: true }, 64.0)
+    }
+
+    /// Every cell of a `SIZE` by `SIZE` grid costs 1, except for `walls`
+    fn cost(walls: &[Vector2<u32>]) -> impl Fn(Vector2<u32>) -> Option<u32> + '_ {
+        move |coords| {
+            if coords.x >= SIZE || coords.y >= SIZE || walls.contains(&coords) {
+                None
+            } else {
+                Some(1)
+            }
+        }
+    }
+
+    fn is_connected(layout: Layout, path: &Path) -> bool {
+        path.hexes
+            .windows(2)
+            .all(|pair| layout.neighbours(pair[0]).contains(&pair[1]))
+    }
+
+    #[test]
+    fn path_over_open_ground_is_as_long_as_the_distance() {
+        let (from, to) = (Vector2::new(1, 1), Vector2::new(7, 5));
+        let path = search_path(layout(), from, to, cost(&[])).unwrap();
+        assert_eq!(path.cost, layout().distance(from, to));
+        assert_eq!(path.hexes.first(), Some(&from));
+        assert_eq!(path.hexes.last(), Some(&to));
+        assert!(is_connected(layout(), &path));
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        // a wall across the grid with a gap at the top
+        let walls: Vec<_> = (0..SIZE - 1).map(|y| Vector2::new(5, y)).collect();
+        let (from, to) = (Vector2::new(2, 0), Vector2::new(8, 0));
+        let path = search_path(layout(), from, to, cost(&walls)).unwrap();
+        assert!(path.cost > layout().distance(from, to));
+        assert!(path.hexes.iter().all(|coords| !walls.contains(coords)));
+        assert!(is_connected(layout(), &path));
+    }
+
+    #[test]
+    fn no_path_to_walled_off_hexes() {
+        let to = Vector2::new(5, 5);
+        let walls = layout().neighbours(to);
+        assert_eq!(
+            search_path(layout(), Vector2::new(0, 0), to, cost(&walls)),
+            None
+        );
+    }
+
+    #[test]
+    fn range_holds_the_hexes_within_budget() {
+        let from = Vector2::new(5, 5);
+        let range = search_range(layout(), from, 2, cost(&[]));
+        assert_eq!(range.costs.len(), 19);
+        for (coords, cost) in &range.costs {
+            assert_eq!(*cost, layout().distance(from, *coords));
+            assert_eq!(range.path_to(*coords).unwrap().cost, *cost);
+        }
+    }
+
+    #[test]
+    fn large_tokens_keep_their_whole_footprint_clear() {
+        let layout = layout();
+        let wall = Vector2::new(5, 5);
+        let occupied = HashSet::new();
+        let walls = [wall];
+        let cost = cost(&walls);
+        let range = search_range(layout, Vector2::new(2, 5), 4, |anchor| {
+            let footprint = layout.footprint(anchor, 3, CentredOn::Tile);
+            footprint_cost(&footprint, &occupied, &cost)
+        });
+        assert!(!range.costs.is_empty());
+        for anchor in range.costs.keys() {
+            assert!(!layout
+                .footprint(*anchor, 3, CentredOn::Tile)
+                .contains(&wall));
+        }
+    }
+
+    #[test]
+    fn tokens_can_leave_the_cells_they_start_on() {
+        let layout = layout();
+        let from = Vector2::new(5, 5);
+        let start = layout.footprint(from, 3, CentredOn::Tile);
+        let occupied = vacate(&start.iter().copied().collect(), &start);
+        let cost = cost(&[]);
+        let range = search_range(layout, from, 1, |anchor| {
+            let footprint = layout.footprint_within(anchor, 3, CentredOn::Tile, (SIZE, SIZE))?;
+            footprint_cost(&footprint, &occupied, &cost)
+        });
+        assert_eq!(range.costs.len(), 7);
+    }
+
+    #[test]
+    fn footprints_cost_as_much_as_their_most_expensive_cell() {
+        let footprint = [Vector2::new(1, 1), Vector2::new(2, 1)];
+        let occupied = HashSet::new();
+        let cost = |coords: Vector2<u32>| Some(coords.x * 2);
+        assert_eq!(footprint_cost(&footprint, &occupied, cost), Some(4));
+        let occupied: HashSet<_> = [Vector2::new(2, 1)].iter().copied().collect();
+        assert_eq!(footprint_cost(&footprint, &occupied, cost), None);
+    }
+}
@@ -0,0 +1,30 @@
+use super::token::InstanceHandle;
+use crate::fgl::framebuffer::FrameBuffer;
+use cgmath::Vector2;
+
+/// Written to the first channel of the click buffer by the grid, nothing
+/// having been drawn where it's still 0
+pub const HEX_PASS: u32 = 1;
+/// Written to the first channel of the click buffer by tokens
+pub const TOKEN_PASS: u32 = 2;
+
+/// What was drawn on top at a pixel of the click buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Picked {
+    Hex(Vector2<u32>),
+    /// The instance may since have been removed
+    Token(InstanceHandle),
+}
+
+impl Picked {
+    /// Reads the click buffer attached to `framebuffer` at `position`,
+    /// counted in pixels from the bottom left
+    pub fn at(framebuffer: &FrameBuffer, attachment: u32, position: Vector2<i32>) -> Option<Self> {
+        let [pass, a, b, _] = framebuffer.read_integer_pixel(attachment, position.x, position.y);
+        match pass {
+            HEX_PASS => Some(Picked::Hex(Vector2::new(a, b))),
+            TOKEN_PASS => Some(Picked::Token(InstanceHandle::from_id(a))),
+            _ => None,
+        }
+    }
+}
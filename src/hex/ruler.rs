@@ -0,0 +1,68 @@
+use super::layout::Layout;
+use crate::render::line::LineRenderer;
+use crate::render::text::TextRenderer;
+use cgmath::{Vector2, Vector4};
+use itertools::Itertools;
+
+const LINE: Vector4<f32> = Vector4::new(0.95, 0.85, 0.2, 0.9);
+const LABEL: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
+const LABEL_BACKGROUND: Vector4<f32> = Vector4::new(0.1, 0.1, 0.1, 0.75);
+
+/// A measurement from one cell to another, through any number of waypoints
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ruler {
+    /// The start, every waypoint, and the current end, in order
+    points: Vec<Vector2<u32>>,
+}
+
+impl Ruler {
+    pub fn new(start: Vector2<u32>) -> Self {
+        Self {
+            points: vec![start, start],
+        }
+    }
+
+    pub fn end(&self) -> Vector2<u32> {
+        *self.points.last().unwrap()
+    }
+
+    pub fn set_end(&mut self, coords: Vector2<u32>) {
+        *self.points.last_mut().unwrap() = coords;
+    }
+
+    /// Fixes the current end as a waypoint and starts a new leg from it
+    pub fn add_waypoint(&mut self) {
+        let end = self.end();
+        if self.points[self.points.len() - 2] != end {
+            self.points.push(end);
+        }
+    }
+
+    pub fn legs(&self) -> impl Iterator<Item = (Vector2<u32>, Vector2<u32>)> + '_ {
+        self.points.iter().copied().tuple_windows()
+    }
+
+    /// Total length over all legs, in cells
+    pub fn distance(&self, layout: Layout) -> u32 {
+        self.legs().map(|(a, b)| layout.distance(a, b)).sum()
+    }
+
+    /// Draws the legs, labelling each waypoint and the end with the distance so far
+    pub fn show(&self, layout: Layout, lines: &mut LineRenderer, text: &mut TextRenderer) {
+        let size = layout.tile_size;
+        let mut total = 0;
+        for (a, b) in self.legs() {
+            let (from, to) = (layout.cell_to_world(a), layout.cell_to_world(b));
+            lines.segment(from, to, size * 0.08, LINE);
+            total += layout.distance(a, b);
+
+            let label = total.to_string();
+            let height = size * 0.35;
+            let at = to + Vector2::new(0.0, size * 0.4);
+            let width = text.width(&label, height) + height * 0.5;
+            let half = Vector2::new(width / 2.0, 0.0);
+            lines.segment(at - half, at + half, height * 1.2, LABEL_BACKGROUND);
+            text.label(&label, at, height, LABEL);
+        }
+    }
+}
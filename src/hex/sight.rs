@@ -0,0 +1,222 @@
+use super::coord::{Hex, LineStep};
+use super::grid::HexGrid;
+use super::overlay::OverlayChannel;
+use cgmath::{Vector2, Vector4};
+
+const CLEAR: Vector4<f32> = Vector4::new(0.2, 0.8, 0.2, 0.4);
+const BLOCKING: Vector4<f32> = Vector4::new(0.9, 0.1, 0.1, 0.6);
+const OBSCURED: Vector4<f32> = Vector4::new(0.1, 0.1, 0.1, 0.4);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cover {
+    None,
+    /// The ground in between hides part of the target
+    Soft,
+    /// The ground in between hides all of the target
+    Hard,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineOfSight {
+    /// Every step of the line, including both ends
+    pub path: Vec<LineStep>,
+    /// Index into `path` of the first step that blocks sight
+    pub blocked_at: Option<usize>,
+}
+
+impl LineOfSight {
+    pub fn is_clear(&self) -> bool {
+        self.blocked_at.is_none()
+    }
+
+    /// Tints the line onto `overlay`: clear hexes, the blocking step, and
+    /// everything behind it each get their own colour
+    pub fn show(&self, overlay: &mut OverlayChannel) {
+        for (i, step) in self.path.iter().enumerate() {
+            let colour = match self.blocked_at {
+                Some(blocked) if i == blocked => BLOCKING,
+                Some(blocked) if i > blocked => OBSCURED,
+                _ => CLEAR,
+            };
+            for hex in step.hexes() {
+                overlay.tint_hex(hex, colour);
+            }
+        }
+    }
+}
+
+impl HexGrid {
+    /// Traces sight from the hex at `from` to the hex at `to`
+    ///
+    /// The end points never block. Where the line runs along an edge it is
+    /// only blocked if the hexes on both sides block sight.
+    pub fn line_of_sight(&self, from: Vector2<u32>, to: Vector2<u32>) -> LineOfSight {
+        trace(self.hex(from).line_to(self.hex(to)), |hex| {
+            self.blocks_sight(hex)
+        })
+    }
+
+    /// Cover a target standing at `target` has against an attacker at `attacker`
+    ///
+    /// Heights are the sizes of the tokens standing on each hex. Sight runs
+    /// from the top of the attacker to the target, and each hex in between
+    /// hides whatever it rises above. Where the line runs along an edge the
+    /// lower of the two hexes counts.
+    pub fn cover(
+        &self,
+        attacker: Vector2<u32>,
+        attacker_height: u32,
+        target: Vector2<u32>,
+        target_height: u32,
+    ) -> Cover {
+        let path = self.hex(attacker).line_to(self.hex(target));
+        let elevation = |hex| {
+            self.offset_coords(hex)
+                .and_then(|coords| self.elevation(coords))
+                .unwrap_or(0)
+        };
+        cover_along(&path, attacker_height, target_height, elevation)
+    }
+
+    fn blocks_sight(&self, hex: Hex) -> bool {
+        self.offset_coords(hex)
+            .and_then(|coords| self.terrain(coords))
+            .map(|terrain| terrain.blocks_sight)
+            .unwrap_or(false)
+    }
+}
+
+/// Finds where `blocks` first blocks sight along `path`, as for `HexGrid::line_of_sight`
+fn trace(path: Vec<LineStep>, blocks: impl Fn(Hex) -> bool) -> LineOfSight {
+    let last = path.len() - 1;
+    let blocked_at = path
+        .iter()
+        .enumerate()
+        .skip(1)
+        .take(last.saturating_sub(1))
+        .find(|(_, step)| step.hexes().all(&blocks))
+        .map(|(i, _)| i);
+    LineOfSight { path, blocked_at }
+}
+
+/// Cover along `path` from its first hex to its last, as for `HexGrid::cover`
+fn cover_along(
+    path: &[LineStep],
+    attacker_height: u32,
+    target_height: u32,
+    elevation: impl Fn(Hex) -> i32,
+) -> Cover {
+    let ground = |step: &LineStep| step.hexes().map(&elevation).min().unwrap() as f32;
+    let n = path.len() - 1;
+    let eye = ground(&path[0]) + attacker_height as f32;
+    let base = ground(&path[n]);
+    let top = base + target_height as f32;
+
+    path.iter()
+        .enumerate()
+        .skip(1)
+        .take(n.saturating_sub(1))
+        .map(|(i, step)| {
+            let t = i as f32 / n as f32;
+            let obstacle = ground(step);
+            if obstacle >= eye + (top - eye) * t {
+                Cover::Hard
+            } else if obstacle > eye + (base - eye) * t {
+                Cover::Soft
+            } else {
+                Cover::None
+            }
+        })
+        .max()
+        .unwrap_or(Cover::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FROM: Hex = Hex::new(0, 0);
+
+    /// A line four hexes east, so step `i` is at `(i, 0)`
+    fn line() -> Vec<LineStep> {
+        FROM.line_to(Hex::new(4, 0))
+    }
+
+    /// A line straight along the edge between `(1, 0)` and `(0, 1)`
+    fn edge_line() -> Vec<LineStep> {
+        FROM.line_to(Hex::new(1, 1))
+    }
+
+    #[test]
+    fn nothing_in_the_way_is_clear() {
+        let sight = trace(line(), |_| false);
+        assert!(sight.is_clear());
+        assert_eq!(sight.path.len(), 5);
+    }
+
+    #[test]
+    fn the_first_blocking_hex_blocks() {
+        let sight = trace(line(), |hex| hex.q >= 2);
+        assert_eq!(sight.blocked_at, Some(2));
+    }
+
+    #[test]
+    fn the_ends_never_block() {
+        let ends = [FROM, Hex::new(4, 0)];
+        assert!(trace(line(), |hex| ends.contains(&hex)).is_clear());
+    }
+
+    #[test]
+    fn edges_block_only_if_both_sides_do() {
+        assert!(matches!(edge_line()[1], LineStep::Edge(_, _)));
+        assert!(trace(edge_line(), |hex| hex == Hex::new(1, 0)).is_clear());
+        assert!(trace(edge_line(), |hex| hex == Hex::new(0, 1)).is_clear());
+        let both = [Hex::new(1, 0), Hex::new(0, 1)];
+        assert_eq!(
+            trace(edge_line(), |hex| both.contains(&hex)).blocked_at,
+            Some(1)
+        );
+    }
+
+    /// Flat ground except for a ridge of `height` halfway along `line`
+    fn ridge(height: i32) -> impl Fn(Hex) -> i32 {
+        move |hex| if hex == Hex::new(2, 0) { height } else { 0 }
+    }
+
+    #[test]
+    fn flat_ground_gives_no_cover() {
+        assert_eq!(cover_along(&line(), 1, 2, ridge(0)), Cover::None);
+    }
+
+    #[test]
+    fn ground_in_the_way_gives_cover() {
+        // sight runs from a height of 1 to between 0 and 2, so 0.5 to 1.5 halfway
+        assert_eq!(cover_along(&line(), 1, 2, ridge(1)), Cover::Soft);
+        assert_eq!(cover_along(&line(), 1, 2, ridge(2)), Cover::Hard);
+        assert_eq!(cover_along(&line(), 3, 2, ridge(1)), Cover::None);
+    }
+
+    #[test]
+    fn standing_higher_sees_over_ground() {
+        let ridge = ridge(1);
+        assert_eq!(cover_along(&line(), 1, 2, &ridge), Cover::Soft);
+        let tower = |hex: Hex| if hex == FROM { 2 } else { ridge(hex) };
+        assert_eq!(cover_along(&line(), 1, 2, tower), Cover::None);
+        let pit = |hex: Hex| if hex == FROM { -2 } else { 0 };
+        assert_eq!(cover_along(&line(), 1, 1, pit), Cover::Hard);
+    }
+
+    #[test]
+    fn edges_give_the_cover_of_the_lower_side() {
+        let one_side = |hex: Hex| if hex == Hex::new(1, 0) { 5 } else { 0 };
+        assert_eq!(cover_along(&edge_line(), 1, 1, one_side), Cover::None);
+        let both_sides = |hex: Hex| {
+            if hex == FROM || hex == Hex::new(1, 1) {
+                0
+            } else {
+                5
+            }
+        };
+        assert_eq!(cover_along(&edge_line(), 1, 1, both_sides), Cover::Hard);
+    }
+}
@@ -0,0 +1,174 @@
+use super::coord::{FractionalHex, Hex};
+use super::grid::HexGrid;
+use super::overlay::OverlayChannel;
+use cgmath::{InnerSpace, Vector2, Vector4};
+
+const AREA: Vector4<f32> = Vector4::new(0.9, 0.3, 0.1, 0.4);
+
+/// Lancer area of effect shapes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// Every hex within N of the target hex
+    Blast(u32),
+    /// Every hex within N of the user, except the user's own
+    Burst(u32),
+    /// A triangle N hexes long, one hex wide next to the user and N wide at the far end
+    Cone(u32),
+    /// A straight line N hexes long, starting next to the user
+    Line(u32),
+}
+
+impl Template {
+    /// Hexes affected when used from `origin`, aimed at `aim`
+    ///
+    /// Blasts are centred on `aim`, bursts ignore it, and cones and lines
+    /// point towards it.
+    pub fn affected(self, origin: Hex, aim: Hex) -> Vec<Hex> {
+        match self {
+            Template::Blast(size) => aim.spiral(size),
+            Template::Burst(size) => origin.spiral(size).split_off(1),
+            Template::Cone(size) => {
+                if aim == origin {
+                    return Vec::new();
+                }
+                let towards = cartesian(aim - origin);
+                // a cone fills the wedge between two neighbouring directions
+                let dir = (0..6)
+                    .max_by(|a, b| {
+                        let wedge = |dir| cartesian(Hex::direction(dir) + Hex::direction(dir + 1));
+                        towards
+                            .dot(wedge(*a))
+                            .partial_cmp(&towards.dot(wedge(*b)))
+                            .unwrap()
+                    })
+                    .unwrap();
+                let (a, b) = (Hex::direction(dir), Hex::direction(dir + 1));
+                let mut hexes = Vec::new();
+                for i in 0..size as i32 {
+                    for j in 0..size as i32 - i {
+                        hexes.push(origin + a * (i + 1) + b * j);
+                    }
+                }
+                hexes
+            }
+            Template::Line(size) => {
+                let distance = origin.distance(aim);
+                if distance == 0 {
+                    return Vec::new();
+                }
+                // nudged so lines along an edge consistently pick one side
+                let start = FractionalHex::new(1e-4, 2e-4);
+                let end = start + FractionalHex::from(aim - origin);
+                (1..=size)
+                    .map(|i| start.lerp(end, i as f32 / distance as f32).round() + origin)
+                    .collect()
+            }
+        }
+    }
+
+    pub fn show(hexes: &[Vector2<u32>], overlay: &mut OverlayChannel) {
+        for coords in hexes {
+            overlay.tint(*coords, AREA);
+        }
+    }
+}
+
+/// Position of a hex relative to the origin in a point-up layout with unit spacing
+///
+/// Flat-top layouts are this rotated by 30°, which turns every angle by the
+/// same amount, so angles between hexes compare the same either way.
+fn cartesian(hex: Hex) -> Vector2<f32> {
+    Vector2::new(
+        3f32.sqrt() * (hex.q as f32 + hex.r as f32 / 2.0),
+        1.5 * hex.r as f32,
+    )
+}
+
+impl HexGrid {
+    /// Hexes on the grid covered by a template used from `origin` at `aim`
+    pub fn template_area(
+        &self,
+        template: Template,
+        origin: Vector2<u32>,
+        aim: Vector2<u32>,
+    ) -> Vec<Vector2<u32>> {
+        template
+            .affected(self.hex(origin), self.hex(aim))
+            .into_iter()
+            .filter_map(|hex| self.offset_coords(hex))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin() -> Hex {
+        Hex::new(2, -1)
+    }
+
+    fn is_unique(hexes: &[Hex]) -> bool {
+        let mut sorted = hexes.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        sorted.len() == hexes.len()
+    }
+
+    #[test]
+    fn blast_covers_everything_within_range_of_the_aim() {
+        let aim = origin() + Hex::new(3, 0);
+        let hexes = Template::Blast(2).affected(origin(), aim);
+        assert_eq!(hexes.len(), 19);
+        assert!(is_unique(&hexes));
+        assert!(hexes.iter().all(|hex| hex.distance(aim) <= 2));
+    }
+
+    #[test]
+    fn burst_leaves_out_the_user() {
+        let hexes = Template::Burst(1).affected(origin(), Hex::ORIGIN);
+        assert_eq!(hexes.len(), 6);
+        assert!(!hexes.contains(&origin()));
+        assert!(hexes.iter().all(|hex| hex.distance(origin()) == 1));
+    }
+
+    #[test]
+    fn cone_widens_towards_the_aim() {
+        for dir in 0..6 {
+            // straight down the middle of the wedge between two directions
+            let (a, b) = (Hex::direction(dir), Hex::direction(dir + 1));
+            let aim = origin() + (a + b) * 2;
+            let hexes = Template::Cone(3).affected(origin(), aim);
+            assert_eq!(hexes.len(), 6);
+            assert!(is_unique(&hexes));
+            for n in 1..=3 {
+                let at = hexes
+                    .iter()
+                    .filter(|hex| hex.distance(origin()) == n)
+                    .count();
+                assert_eq!(at, n as usize);
+            }
+            assert!(hexes.contains(&(origin() + a)));
+            assert!(hexes.contains(&(origin() + a * 3)));
+            assert!(hexes.contains(&(origin() + a + b * 2)));
+        }
+    }
+
+    #[test]
+    fn line_runs_from_next_to_the_user() {
+        for dir in 0..6 {
+            let aim = origin() + Hex::direction(dir) * 2;
+            let hexes = Template::Line(4).affected(origin(), aim);
+            let expected: Vec<_> = (1..=4)
+                .map(|i| origin() + Hex::direction(dir) * i)
+                .collect();
+            assert_eq!(hexes, expected);
+        }
+    }
+
+    #[test]
+    fn aiming_at_the_user_gives_no_cone_or_line() {
+        assert!(Template::Cone(3).affected(origin(), origin()).is_empty());
+        assert!(Template::Line(3).affected(origin(), origin()).is_empty());
+    }
+}
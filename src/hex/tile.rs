@@ -0,0 +1,168 @@
+use image::codecs::{gif::GifDecoder, png::PngDecoder};
+use image::{AnimationDecoder, DynamicImage, GenericImageView, ImageResult};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long frames without a delay of their own are shown, as browsers do
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// One entry of a tileset, either a still image or frames shown in a loop
+#[derive(Clone)]
+pub enum Tile {
+    Still(DynamicImage),
+    Animated(Animation),
+}
+
+/// Frames and how long each is shown for, never empty
+#[derive(Clone)]
+pub struct Animation(Vec<(DynamicImage, Duration)>);
+
+impl From<DynamicImage> for Tile {
+    fn from(image: DynamicImage) -> Self {
+        Tile::Still(image)
+    }
+}
+
+impl Tile {
+    /// A tile showing `frames` in a loop, `None` if there are no frames
+    pub fn animated(mut frames: Vec<(DynamicImage, Duration)>) -> Option<Self> {
+        match frames.len() {
+            0 => None,
+            1 => Some(Tile::Still(frames.remove(0).0)),
+            _ => Some(Tile::Animated(Animation(frames))),
+        }
+    }
+
+    /// Cuts a strip of frames laid out left to right into one per duration,
+    /// `None` if there are no durations or the strip doesn't split evenly
+    pub fn from_strip(strip: &DynamicImage, durations: &[Duration]) -> Option<Self> {
+        let count = durations.len() as u32;
+        if count == 0 || !strip.width().is_multiple_of(count) {
+            return None;
+        }
+        let width = strip.width() / count;
+        let frames = durations
+            .iter()
+            .enumerate()
+            .map(|(n, duration)| {
+                let frame = strip.crop_imm(n as u32 * width, 0, width, strip.height());
+                (frame, *duration)
+            })
+            .collect();
+        Tile::animated(frames)
+    }
+
+    /// Loads an image, keeping every frame of an animated GIF or PNG
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let frames = match extension.as_deref() {
+            Some("gif") => {
+                let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+                decoder.into_frames().collect_frames()?
+            }
+            Some("png") | Some("apng") => {
+                let decoder = PngDecoder::new(BufReader::new(File::open(path)?))?;
+                if !decoder.is_apng() {
+                    return image::open(path).map(Tile::Still);
+                }
+                decoder.apng().into_frames().collect_frames()?
+            }
+            _ => return image::open(path).map(Tile::Still),
+        };
+        let frames = frames
+            .into_iter()
+            .map(|frame| {
+                let (numerator, denominator) = frame.delay().numer_denom_ms();
+                let delay = Duration::from_millis(u64::from(numerator / denominator.max(1)));
+                let delay = if delay.as_millis() == 0 {
+                    DEFAULT_DELAY
+                } else {
+                    delay
+                };
+                (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+            })
+            .collect();
+        Tile::animated(frames).ok_or_else(|| {
+            image::ImageError::Decoding(image::error::DecodingError::new(
+                image::error::ImageFormatHint::PathExtension(path.to_path_buf()),
+                "no frames",
+            ))
+        })
+    }
+
+    /// The image shown when the tile isn't animated
+    pub fn first_frame(&self) -> &DynamicImage {
+        match self {
+            Tile::Still(image) => image,
+            Tile::Animated(Animation(frames)) => &frames[0].0,
+        }
+    }
+
+    pub fn frames(&self) -> Vec<&DynamicImage> {
+        match self {
+            Tile::Still(image) => vec![image],
+            Tile::Animated(Animation(frames)) => frames.iter().map(|(image, _)| image).collect(),
+        }
+    }
+
+    /// Frame durations, none for still tiles and animations of one frame
+    pub(super) fn durations(&self) -> Vec<Duration> {
+        match self {
+            Tile::Animated(Animation(frames)) if frames.len() > 1 => {
+                frames.iter().map(|(_, duration)| *duration).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animations_need_frames() {
+        let frame = || (DynamicImage::new_rgba8(1, 1), DEFAULT_DELAY);
+        assert!(Tile::animated(Vec::new()).is_none());
+        assert!(matches!(
+            Tile::animated(vec![frame()]),
+            Some(Tile::Still(_))
+        ));
+        let tile = Tile::animated(vec![frame(), frame()]).unwrap();
+        assert_eq!(tile.durations(), vec![DEFAULT_DELAY; 2]);
+    }
+
+    #[test]
+    fn strips_split_into_frames() {
+        let strip = DynamicImage::new_rgba8(12, 4);
+        let durations = [
+            Duration::from_millis(50),
+            Duration::from_millis(150),
+            DEFAULT_DELAY,
+        ];
+        let tile = Tile::from_strip(&strip, &durations).unwrap();
+        assert_eq!(tile.durations(), durations.to_vec());
+        assert!(tile
+            .frames()
+            .iter()
+            .all(|frame| frame.dimensions() == (4, 4)));
+        assert!(matches!(
+            Tile::from_strip(&strip, &durations[..1]),
+            Some(Tile::Still(_))
+        ));
+    }
+
+    #[test]
+    fn strips_must_split_evenly() {
+        let strip = DynamicImage::new_rgba8(10, 4);
+        assert!(Tile::from_strip(&strip, &[]).is_none());
+        assert!(Tile::from_strip(&strip, &[DEFAULT_DELAY; 3]).is_none());
+        assert!(Tile::from_strip(&strip, &[DEFAULT_DELAY; 2]).is_some());
+    }
+}
@@ -1,51 +1,97 @@
+use super::layout::{Layout, GRIDLESS_SUBDIVISIONS};
+use super::pick::TOKEN_PASS;
 use crate::fgl::{self, Bindable, Program};
 use cgmath::{Vector2, Zero};
 use image::{DynamicImage, GenericImageView};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use fgl::{ProgramBuilder, Shader};
 
 const VERT: &str = include_str!("../../resources/shaders/token.vert");
 const FRAG: &str = include_str!("../../resources/shaders/token.frag");
+/// Width and height of the mask textures
+const MASK_SIZE: u32 = 256;
+/// Samples taken per pixel along each axis, to smooth the edges of masks
+const MASK_SAMPLES: u32 = 4;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TokenHandle(usize);
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+impl TokenHandle {
+    /// Position of the token in the order it was added to its manager
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Refers to one instance for as long as it exists, however others are added or removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceHandle(u32);
+
+impl InstanceHandle {
+    /// What the token shaders write to the click buffer for the instance
+    pub(super) fn id(self) -> u32 {
+        self.0
+    }
+
+    pub(super) fn from_id(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CentredOn {
     Tile,
+    /// Centred on the top (or upper right, for flat-top and square grids)
+    /// corner of the cell if `point_up`, or the opposite corner otherwise
     Corner { point_up: bool },
 }
 
+/// How a token's art is shaped to the cells it covers
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Mask {
     None = 0,
+    /// A plate in the shape of the footprint is drawn under the art
     Behind = 1,
+    /// Art outside the footprint is cut away
     Clip = 2,
 }
 
+/// What the token shaders need to know about one token
 #[repr(C)]
 struct TokenUniform {
+    /// Of the art, in world units
     size: Vector2<f32>,
+    /// Of the area the mask texture covers, zero without a mask
     mask_size: Vector2<f32>,
     mask: Mask,
 }
 
 pub struct TokenManager {
-    tile_size: f32,
+    layout: Layout,
     tokens: Vec<Token>,
+    /// Grouped by token so each token is drawn in one batch
     instances: Vec<TokenInstance>,
-    vbos: [fgl::VertexBuffer; 2],
+    /// The handle of each instance, in the same order
+    handles: Vec<InstanceHandle>,
+    next_handle: u32,
+    /// The quad, instance offsets and instance handles
+    vbos: [fgl::VertexBuffer; 3],
     vao: fgl::VertexAttribObject,
-    masks: Vec<fgl::texture::Texture2D>,
-    needs_update: bool,
+    /// Shared by every token with the same footprint, which depends on size and centring
+    masks: HashMap<(u32, CentredOn), (fgl::texture::Texture2D, Vector2<f32>)>,
     program: Program,
 }
 
 impl TokenManager {
-    pub fn new(tile_size: f32, tokens: impl IntoIterator<Item=Token>) -> Result<(Self, Vec<TokenHandle>), String> {
+    pub fn new(
+        layout: Layout,
+        tokens: impl IntoIterator<Item = Token>,
+    ) -> Result<(Self, Vec<TokenHandle>), String> {
         let vao = fgl::VertexAttribObject::new();
-        let mut vbos: [fgl::VertexBuffer; 2] = fgl::VertexBuffer::new_array();
+        let mut vbos: [fgl::VertexBuffer; 3] = fgl::VertexBuffer::new_array();
 
         vbos[0].alloc_with(
             &fgl::consts::QUAD,
@@ -57,13 +103,6 @@ impl TokenManager {
             fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
         );
 
-        vao.vertex_attribute_array(
-            &vbos[1],
-            fgl::VertexAttribArray::<f32>::with_id(1)
-                .with_components_per_value(2)
-                .with_divisor(6),
-        );
-
         let program = ProgramBuilder::default()
             .attach_shader(Shader::from_source(fgl::ShaderType::Fragment, FRAG)?)
             .attach_shader(Shader::from_source(fgl::ShaderType::Vertex, VERT)?)
@@ -72,73 +111,220 @@ impl TokenManager {
         let tokens: Vec<_> = tokens.into_iter().collect();
         let len = tokens.len();
 
-        Ok((
-            Self {
-                tokens,
-                instances: Vec::new(),
-                vbos,
-                vao,
-                masks: Vec::new(),
-                tile_size,
-                needs_update: false,
-                program,
-            },
-            (0..len).map(TokenHandle).collect(),
-        ))
+        let mut manager = Self {
+            tokens,
+            instances: Vec::new(),
+            handles: Vec::new(),
+            next_handle: 0,
+            vbos,
+            vao,
+            masks: HashMap::new(),
+            layout,
+            program,
+        };
+        manager.build_masks();
+        Ok((manager, (0..len).map(TokenHandle).collect()))
+    }
+
+    /// Moves every instance to where its cell is under `layout`
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.masks.clear();
+        self.build_masks();
+        self.upload();
     }
 
     pub fn append_tokens(&mut self, tokens: impl IntoIterator<Item=Token>) -> Vec<TokenHandle> {
         let old_len = self.tokens.len();
         self.tokens.extend(tokens);
+        self.build_masks();
         (old_len..self.tokens.len()).map(TokenHandle).collect()
     }
 
-    pub fn append_instances(&mut self, instances: &[TokenInstance]) {
-        self.instances.extend_from_slice(instances);
-        self.instances.sort_by_key(|instance| instance.token);
-        let data: Vec<_> = self
-            .instances
+    pub fn append_instances(&mut self, instances: &[TokenInstance]) -> Vec<InstanceHandle> {
+        let handles = instances
             .iter()
-            .map(|x| {
-                super::grid_to_world(x.coords, self.tile_size)
-                    + match self.tokens[x.token.0].centred_on {
-                        CentredOn::Tile => Vector2::zero(),
-                        CentredOn::Corner { .. } => super::corner_offset(self.tile_size, 0),
-                    }
+            .map(|instance| {
+                let handle = InstanceHandle(self.next_handle);
+                self.next_handle += 1;
+                let idx = self
+                    .instances
+                    .partition_point(|other| other.token <= instance.token);
+                self.instances.insert(idx, *instance);
+                self.handles.insert(idx, handle);
+                handle
             })
             .collect();
-        self.vbos[1].alloc_with(
-            &data,
-            fgl::AccessFrequency::Dynamic,
-            fgl::AccessType::Draw,
-        );
+        self.upload();
+        handles
+    }
+
+    /// Every instance, grouped by token
+    pub fn instances(&self) -> &[TokenInstance] {
+        &self.instances
+    }
+
+    pub fn instance(&self, handle: InstanceHandle) -> Option<&TokenInstance> {
+        self.position(handle).map(|idx| &self.instances[idx])
     }
 
-    pub fn find_instances_at(
+    /// Moves an instance to `coords`, returning where it was
+    pub fn move_instance(
         &mut self,
+        handle: InstanceHandle,
         coords: Vector2<u32>,
-    ) -> impl Iterator<Item = &mut TokenInstance> {
-        self.needs_update = true;
+    ) -> Option<Vector2<u32>> {
+        let idx = self.position(handle)?;
+        let from = std::mem::replace(&mut self.instances[idx].coords, coords);
+        let offset = self.instance_offset(&self.instances[idx]);
+        self.vbos[1].replace_sub_data(idx, &[offset]);
+        Some(from)
+    }
+
+    pub fn remove_instance(&mut self, handle: InstanceHandle) -> Option<TokenInstance> {
+        let idx = self.position(handle)?;
+        self.handles.remove(idx);
+        let instance = self.instances.remove(idx);
+        self.upload();
+        Some(instance)
+    }
+
+    /// Moves every instance by `shift`, as when the grid grew or shrank on its
+    /// left or bottom, removing those that end up outside `dimensions`
+    pub fn shift_instances(&mut self, shift: (i64, i64), dimensions: (u32, u32)) {
+        let (instances, handles) = self
+            .instances
+            .iter()
+            .zip(&self.handles)
+            .filter_map(|(instance, handle)| {
+                let x = instance.coords.x as i64 + shift.0;
+                let y = instance.coords.y as i64 + shift.1;
+                let inside =
+                    (0..dimensions.0 as i64).contains(&x) && (0..dimensions.1 as i64).contains(&y);
+                let moved = TokenInstance {
+                    coords: Vector2::new(x as u32, y as u32),
+                    token: instance.token,
+                };
+                inside.then_some((moved, *handle))
+            })
+            .unzip();
+        self.instances = instances;
+        self.handles = handles;
+        self.upload();
+    }
+
+    /// Handles of the instances covering the cell at `coords`
+    pub fn handles_at(&self, coords: Vector2<u32>) -> impl Iterator<Item = InstanceHandle> + '_ {
         self.instances
-            .iter_mut()
-            .filter(move |x| x.coords == coords)
-    }
-
-    pub fn update(&mut self) {
-        if self.needs_update {
-            self.instances.sort_by_key(|instance| instance.token);
-            let data: Vec<_> = self
-                .instances
-                .iter()
-                .map(|x| {
-                    super::grid_to_world(x.coords, self.tile_size)
-                        + match self.tokens[x.token.0].centred_on {
-                            CentredOn::Tile => Vector2::zero(),
-                            CentredOn::Corner { .. } => super::corner_offset(self.tile_size, 0),
-                        }
-                })
-                .collect();
-            self.vbos[1].replace_sub_data(0, &data);
+            .iter()
+            .zip(&self.handles)
+            .filter(move |(x, _)| self.covered(x).contains(&coords))
+            .map(|(_, handle)| *handle)
+    }
+
+    /// Instances covering the cell at `coords`
+    pub fn instances_at(&self, coords: Vector2<u32>) -> impl Iterator<Item = &TokenInstance> {
+        self.instances
+            .iter()
+            .filter(move |x| self.covered(x).contains(&coords))
+    }
+
+    /// Instances covering any of the cells in `area`
+    pub fn instances_within<'a>(
+        &'a self,
+        area: &'a [Vector2<u32>],
+    ) -> impl Iterator<Item = &'a TokenInstance> {
+        self.instances
+            .iter()
+            .filter(move |x| self.covered(x).iter().any(|coords| area.contains(coords)))
+    }
+
+    /// Cells other tokens can't move into
+    pub fn occupied(&self) -> HashSet<Vector2<u32>> {
+        self.instances
+            .iter()
+            .flat_map(|x| self.covered(x))
+            .collect()
+    }
+
+    /// How tall `instance` stands, for cover
+    pub fn height(&self, instance: &TokenInstance) -> u32 {
+        self.tokens[instance.token.0].nominal_size.max(1)
+    }
+
+    /// The size and centring that decide which cells `instance` covers around its anchor
+    pub fn footprint(&self, instance: &TokenInstance) -> (u32, CentredOn) {
+        let token = &self.tokens[instance.token.0];
+        (token.nominal_size, token.centred_on)
+    }
+
+    /// Every cell `instance` covers
+    pub fn covered(&self, instance: &TokenInstance) -> Vec<Vector2<u32>> {
+        covered_by(&self.tokens, self.layout, instance)
+    }
+
+    fn position(&self, handle: InstanceHandle) -> Option<usize> {
+        self.handles.iter().position(|x| *x == handle)
+    }
+
+    /// Uploads every instance's position and handle, after instances were added or removed
+    fn upload(&mut self) {
+        if !self.instances.is_empty() {
+            let data = self.instance_offsets();
+            self.vbos[1].alloc_with(
+                &data,
+                fgl::AccessFrequency::Dynamic,
+                fgl::AccessType::Draw,
+            );
+            let ids: Vec<_> = self.handles.iter().map(|handle| handle.id()).collect();
+            self.vbos[2].alloc_with(&ids, fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+        }
+    }
+
+    /// World positions the instances' art is centred on
+    fn instance_offsets(&self) -> Vec<Vector2<f32>> {
+        self.instances
+            .iter()
+            .map(|x| self.instance_offset(x))
+            .collect()
+    }
+
+    fn instance_offset(&self, instance: &TokenInstance) -> Vector2<f32> {
+        let centred_on = self.tokens[instance.token.0].centred_on;
+        centre(self.layout, instance.coords, centred_on)
+    }
+
+    /// Generates the masks tokens need that there aren't any yet
+    fn build_masks(&mut self) {
+        for token in &self.tokens {
+            let key = (token.nominal_size, token.centred_on);
+            if token.mask != Mask::None && !self.masks.contains_key(&key) {
+                let mask = footprint_mask(self.layout, token.nominal_size, token.centred_on);
+                self.masks.insert(key, mask);
+            }
+        }
+    }
+
+    fn uniform(&self, token: &Token) -> TokenUniform {
+        let size = if token.scale {
+            let Vector2 { x, y } = token.dimensions;
+            let across = self.layout.tile_size * token.nominal_size.max(1) as f32;
+            (if x > y {
+                Vector2::new(1.0, y as f32 / x as f32)
+            } else {
+                Vector2::new(x as f32 / y as f32, 1.0)
+            }) * across
+        } else {
+            token.dimensions.map(|x| x as f32)
+        };
+        let mask_size = match token.mask {
+            Mask::None => Vector2::zero(),
+            _ => self.masks[&(token.nominal_size, token.centred_on)].1,
+        };
+        TokenUniform {
+            size,
+            mask_size,
+            mask: token.mask,
         }
     }
 
@@ -153,33 +339,55 @@ impl TokenManager {
             .map(|g| (g.0, g.1.count()))
             .collect();
         self.program.uniform_mat4("projection", &projection);
-        self.program.uniform_u32("renderpass", 125);
+        self.program.uniform_u32("renderpass", TOKEN_PASS);
         let mut first = 0;
         for (handle, batch_size) in batches {
             let token = &self.tokens[handle.0];
+            let uniform = self.uniform(token);
+            // the quad covers both the art and the mask, each scaled to its part
+            let extent = Vector2::new(
+                uniform.size.x.max(uniform.mask_size.x),
+                uniform.size.y.max(uniform.mask_size.y),
+            );
+            self.program.uniform_vec2("dimensions", extent);
             self.program.uniform_vec2(
-                "dimensions",
-                if token.scale {
-                    let Vector2 { x, y } = token.dimensions;
-                    (if x > y {
-                        Vector2::new(1.0, y as f32 / x as f32)
-                    } else {
-                        Vector2::new(x as f32 / y as f32, 1.0)
-                    }) * self.tile_size
-                } else {
-                    token.dimensions.map(|x| x as f32)
-                },
+                "art_scale",
+                Vector2::new(extent.x / uniform.size.x, extent.y / uniform.size.y),
             );
+            self.program.uniform_i32("mask", uniform.mask as i32);
             token.texture.bind(0);
             self.program.uniform_i32("token", 0);
-            unsafe {
-                gl::DrawArraysInstanced(
-                    gl::TRIANGLES,
-                    first * 6,
-                    6i32,
-                    (batch_size * 6) as i32,
+            if uniform.mask != Mask::None {
+                let (texture, _) = &self.masks[&(token.nominal_size, token.centred_on)];
+                texture.bind(1);
+                self.program.uniform_i32("mask_texture", 1);
+                self.program.uniform_vec2(
+                    "mask_scale",
+                    Vector2::new(
+                        extent.x / uniform.mask_size.x,
+                        extent.y / uniform.mask_size.y,
+                    ),
                 );
             }
+            // instanced draws can't start part way into the instances, so the
+            // offsets and handles are read from where this batch's begin instead
+            self.vao.vertex_attribute_array(
+                &self.vbos[1],
+                fgl::VertexAttribArray::<f32>::with_id(1)
+                    .with_components_per_value(2)
+                    .with_divisor(1)
+                    .with_offset(first * std::mem::size_of::<Vector2<f32>>() as i32),
+            );
+            self.vao.vertex_attribute_array(
+                &self.vbos[2],
+                fgl::VertexAttribArray::<u32>::with_id(2)
+                    .integer()
+                    .with_divisor(1)
+                    .with_offset(first * std::mem::size_of::<u32>() as i32),
+            );
+            unsafe {
+                gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, batch_size as i32);
+            }
             first += batch_size as i32;
         }
     }
@@ -213,6 +421,77 @@ impl Token {
     }
 }
 
+/// Where a token at `anchor` is centred
+fn centre(layout: Layout, anchor: Vector2<u32>, centred_on: CentredOn) -> Vector2<f32> {
+    layout.cell_to_world(anchor)
+        + match centred_on {
+            CentredOn::Tile => Vector2::zero(),
+            CentredOn::Corner { point_up } => layout.corner_offset(point_up),
+        }
+}
+
+/// A texture that is opaque over the cells a token covers, and the size of
+/// the area around the token's centre it spans
+fn footprint_mask(
+    layout: Layout,
+    size: u32,
+    centred_on: CentredOn,
+) -> (fgl::texture::Texture2D, Vector2<f32>) {
+    // far enough from the edges that none of the footprint is cut off
+    let anchor = Vector2::new(1, 1) * (size.max(1) * GRIDLESS_SUBDIVISIONS + 2);
+    let cells: HashSet<_> = layout
+        .footprint(anchor, size, centred_on)
+        .into_iter()
+        .collect();
+    let middle = centre(layout, anchor, centred_on);
+
+    let half = layout.cell_size() / 2.0;
+    let mut extent = Vector2::new(0.0f32, 0.0f32);
+    for coords in &cells {
+        let mut corners = layout.corners(*coords);
+        if corners.is_empty() {
+            // gridless points stand for the square around them
+            let centre = layout.cell_to_world(*coords);
+            corners = vec![
+                centre - Vector2::new(half, half),
+                centre + Vector2::new(half, half),
+            ];
+        }
+        for corner in corners {
+            extent.x = extent.x.max((corner.x - middle.x).abs() * 2.0);
+            extent.y = extent.y.max((corner.y - middle.y).abs() * 2.0);
+        }
+    }
+
+    let samples = (MASK_SAMPLES * MASK_SAMPLES) as f32;
+    let image = image::RgbaImage::from_fn(MASK_SIZE, MASK_SIZE, |x, y| {
+        let mut inside = 0;
+        for sy in 0..MASK_SAMPLES {
+            for sx in 0..MASK_SAMPLES {
+                let u = ((x * MASK_SAMPLES + sx) as f32 + 0.5) / (MASK_SIZE * MASK_SAMPLES) as f32;
+                let v = ((y * MASK_SAMPLES + sy) as f32 + 0.5) / (MASK_SIZE * MASK_SAMPLES) as f32;
+                // the first row is the top, as with the art
+                let world = middle + Vector2::new((u - 0.5) * extent.x, (0.5 - v) * extent.y);
+                if layout
+                    .world_to_cell(world)
+                    .is_some_and(|coords| cells.contains(&coords))
+                {
+                    inside += 1;
+                }
+            }
+        }
+        let alpha = (inside as f32 / samples * 255.0).round() as u8;
+        image::Rgba([255, 255, 255, alpha])
+    });
+    let texture = fgl::texture::Texture2D::from_image(DynamicImage::ImageRgba8(image));
+    (texture, extent)
+}
+
+fn covered_by(tokens: &[Token], layout: Layout, instance: &TokenInstance) -> Vec<Vector2<u32>> {
+    let token = &tokens[instance.token.0];
+    layout.footprint(instance.coords, token.nominal_size, token.centred_on)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct TokenInstance {
     pub coords: Vector2<u32>,
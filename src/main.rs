@@ -1,14 +1,26 @@
 mod hex;
-use hex::grid::HexGridBuilder;
-use hex::token::{CentredOn, Mask, Token, TokenInstance, TokenManager};
+use hex::calibration::Calibration;
+use hex::grid::{Edge, GridError, HexGrid, Terrain, BASE_LAYER};
+use hex::layer::Orientation;
+use hex::layout::Shape;
+use hex::outline::{GridOutline, OutlineStyle};
+use hex::path::MovementRange;
+use hex::pick::Picked;
+use hex::ruler::Ruler;
+use hex::template::Template;
+use hex::token::{CentredOn, InstanceHandle, Mask, TokenManager};
 
 mod fgl;
 mod render;
 mod gui;
+mod export;
+mod map;
+mod tiled;
 
 use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector2, Vector3, Vector4, Zero};
 use glutin::{
-    dpi::PhysicalPosition,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::VirtualKeyCode,
     event_loop::{EventLoop, EventLoopProxy},
     window::WindowBuilder,
     ContextBuilder,
@@ -18,22 +30,243 @@ use glutin::platform::windows::{WindowBuilderExtWindows, WindowExtWindows};
 #[cfg(target_os = "linux")]
 use glutin::platform::unix::{WindowBuilderExtUnix, WindowExtUnix};
 
+use map::{
+    GridDocument, InstanceDocument, LayerDocument, MapDocument, Metadata, Tileset, TokenDocument,
+};
+use export::ExportOptions;
+use tiled::TiledMap;
 use render::compose::QuadComposer;
+use render::line::LineRenderer;
+use render::text::TextRenderer;
 use tokio::runtime::Runtime;
 
 use crate::{fgl::texture::Filter, render::compose::Quad};
 
 pub enum NetworkEvent {}
 
+/// The selected token, where it started, how far it can go, and where the path shown leads
+type Selection = (InstanceHandle, Vector2<u32>, MovementRange, Option<Vector2<u32>>);
+
 async fn other(_: EventLoopProxy<NetworkEvent>) {}
 
 const TEST_TILE1: &str = "tiles/Spaceland.Space/C. Anomalies/anom-008.png";
 const TEST_TILE2: &str = "tiles/Spaceland.Space/C. Anomalies/anom-004.png";
 
 const TEST_TOKEN: &str = "mechs/HA GENGHIS.png";
+const TEST_MAP: &str = "maps/battlemap.png";
+/// Where the map is saved to if it wasn't loaded from a file
+const DEFAULT_SAVE: &str = "map.ron";
+const EXPORT_PATH: &str = "map.png";
+
+const MOVEMENT_SPEED: u32 = 4;
+/// The framebuffer's colour attachment the grid and tokens write what was drawn where to
+const CLICK_ATTACHMENT: u32 = 1;
+/// How often the map is redrawn while it has animated tiles
+const ANIMATION_FRAME: std::time::Duration = std::time::Duration::from_millis(33);
+/// Held while dragging to measure instead of scrolling
+const MEASURE_KEY: VirtualKeyCode = VirtualKeyCode::M;
+const OUTLINE_KEY: VirtualKeyCode = VirtualKeyCode::G;
+const LABELS_KEY: VirtualKeyCode = VirtualKeyCode::L;
+/// Starts marking cell centres on the background, and applies them when pressed again
+const CALIBRATE_KEY: VirtualKeyCode = VirtualKeyCode::C;
+const SAVE_KEY: VirtualKeyCode = VirtualKeyCode::S;
+/// Takes the selected token off the map
+const REMOVE_KEY: VirtualKeyCode = VirtualKeyCode::Delete;
+/// Renders the whole map to `EXPORT_PATH`, with grid lines if they are showing
+const EXPORT_KEY: VirtualKeyCode = VirtualKeyCode::E;
+/// Rows or columns the arrow keys add on that side of the map, or take away
+/// with shift held. Staggered ones only change in pairs.
+const RESIZE_STEP: u32 = 2;
 
 const VERT: &str = include_str!("../resources/shaders/grid.vert");
 const FRAG: &str = include_str!("../resources/shaders/grid.frag");
+const OVERLAY_VERT: &str = include_str!("../resources/shaders/overlay.vert");
+const OVERLAY_FRAG: &str = include_str!("../resources/shaders/overlay.frag");
+
+const MOVEMENT_CHANNEL: &str = "movement";
+const PATH_CHANNEL: &str = "path";
+const SIGHT_CHANNEL: &str = "sight";
+const TEMPLATE_CHANNEL: &str = "template";
+
+/// The matrix the map is drawn with
+fn view_matrix(projection: Matrix4<f32>, scale: f32, scroll: Vector2<f32>) -> Matrix4<f32> {
+    projection
+        * Matrix4::from_nonuniform_scale(scale, scale, 1.0)
+        * Matrix4::from_translation(Vector3::new(scroll.x, scroll.y, 0f32))
+}
+
+/// The map used when none is given on the command line
+fn demo_map() -> MapDocument {
+    let (width, height) = (50, 50);
+    let tiles = [TEST_TILE1, TEST_TILE2];
+    let tile_size = tiles
+        .iter()
+        .map(|path| {
+            let (x, y) = image::image_dimensions(path).unwrap();
+            u32::max(x, y)
+        })
+        .max()
+        .unwrap();
+
+    let cells = (width * height) as usize;
+    let idx = |x: u32, y: u32| (x + width * y) as usize;
+    let mut contents = vec![Some(0); cells];
+    let mut orientations = vec![Orientation::default(); cells];
+    contents[idx(2, 1)] = Some(1);
+    orientations[idx(2, 1)] = Orientation::rotated(1);
+    let mut terrain = vec![Terrain::default(); cells];
+    terrain[idx(2, 1)].blocks_sight = true;
+    let mut elevation = vec![0; cells];
+    elevation[idx(4, 3)] = 2;
+
+    MapDocument {
+        version: map::VERSION,
+        metadata: Metadata {
+            name: "Demo".to_string(),
+            ..Default::default()
+        },
+        grid: GridDocument {
+            shape: Shape::Hex { point_up: true },
+            tile_size: tile_size as f32,
+            origin: (0.0, 0.0),
+            width,
+            height,
+            terrain,
+            elevation,
+        },
+        tilesets: vec![Tileset {
+            name: "anomalies".to_string(),
+            tiles: tiles.iter().map(|path| path.to_string()).collect(),
+            strips: Default::default(),
+        }],
+        layers: vec![LayerDocument {
+            name: BASE_LAYER.to_string(),
+            tileset: 0,
+            contents,
+            orientations,
+            visible: true,
+            opacity: 1.0,
+            gm_only: false,
+        }],
+        background: std::path::Path::new(TEST_MAP)
+            .exists()
+            .then(|| TEST_MAP.to_string()),
+        tokens: vec![TokenDocument {
+            image: TEST_TOKEN.to_string(),
+            nominal_size: 0,
+            scale: true,
+            mask: Mask::None,
+            centred_on: CentredOn::Corner { point_up: true },
+        }],
+        instances: vec![InstanceDocument {
+            token: 0,
+            coords: (3, 2),
+        }],
+    }
+}
+
+/// Grows the map by `RESIZE_STEP` along `edge`, or shrinks it, keeping every
+/// hex and token where it was on screen
+fn resize(
+    hex_grid: &mut HexGrid,
+    token_manager: &mut TokenManager,
+    edge: Edge,
+    grow: bool,
+) -> Result<(), GridError> {
+    if grow {
+        hex_grid.grow(edge, RESIZE_STEP)?;
+    } else {
+        hex_grid.shrink(edge, RESIZE_STEP)?;
+    }
+    let step = RESIZE_STEP as i64 * if grow { 1 } else { -1 };
+    let shift = match edge {
+        Edge::Left => (step, 0),
+        Edge::Bottom => (0, step),
+        Edge::Right | Edge::Top => (0, 0),
+    };
+    // the cells that moved are as far from the origin as the origin has to move
+    let layout = hex_grid.layout();
+    let moved = Vector2::new(shift.0.unsigned_abs() as u32, shift.1.unsigned_abs() as u32);
+    let offset = layout.cell_to_world(moved) - layout.cell_to_world(Vector2::new(0, 0));
+    let origin = if grow {
+        layout.origin - offset
+    } else {
+        layout.origin + offset
+    };
+    hex_grid.set_layout(layout.with_origin(origin));
+    token_manager.set_layout(hex_grid.layout());
+    token_manager.shift_instances(shift, hex_grid.dimensions());
+    Ok(())
+}
+
+fn hex_under_cursor(
+    grid: &HexGrid,
+    cursor: PhysicalPosition<f64>,
+    window: PhysicalSize<u32>,
+    view: Matrix4<f32>,
+) -> Option<Vector2<u32>> {
+    hex::screen_to_world(
+        Vector2::new(cursor.x as f32, cursor.y as f32),
+        Vector2::new(window.width as f32, window.height as f32),
+        view,
+    )
+    .and_then(|world| grid.hex_at(world))
+}
+
+/// What was drawn on top under the cursor in the last frame
+/// The framebuffer the scene is drawn into before it's put on screen,
+/// together with the images attached to it
+struct Offscreen {
+    fb: fgl::framebuffer::FrameBuffer,
+    colour: fgl::texture::Texture2D,
+    /// What was drawn where, for `Picked`
+    click: fgl::texture::Texture2D,
+    depth: fgl::framebuffer::RenderBuffer,
+}
+
+impl Offscreen {
+    fn new(size: PhysicalSize<u32>) -> Self {
+        let (width, height) = (size.width as i32, size.height as i32);
+        let colour =
+            fgl::texture::Texture2D::with_dimensions(width, height, fgl::texture::Format::Rgba);
+        colour.set_min_filter(Filter::Nearest);
+        colour.set_mag_filter(Filter::Nearest);
+        let click =
+            fgl::texture::Texture2D::with_dimensions(width, height, fgl::texture::Format::Rgba32Ui);
+        let depth = fgl::framebuffer::RenderBuffer::new();
+        depth.alloc(
+            size.width,
+            size.height,
+            fgl::framebuffer::Format::DepthStencil,
+            0,
+        );
+        let offscreen = Offscreen {
+            fb: fgl::framebuffer::FrameBuffer::new(),
+            colour,
+            click,
+            depth,
+        };
+        offscreen.attach();
+        offscreen
+    }
+
+    fn attach(&self) {
+        use fgl::framebuffer::Attachment;
+        self.fb.attach_texture2d(&self.colour, Attachment::Color(0));
+        self.fb.attach_texture2d(&self.click, Attachment::Color(CLICK_ATTACHMENT));
+        self.fb.attach_renderbuffer(&self.depth, Attachment::DepthStencil);
+        self.fb.set_draw_buffers(&[Some(0), Some(CLICK_ATTACHMENT)]);
+    }
+
+    /// What was clicked with the cursor at `cursor`, `None` outside the window
+    fn picked(&self, cursor: PhysicalPosition<f64>, window: PhysicalSize<u32>) -> Option<Picked> {
+        let (x, y) = (cursor.x as i32, window.height as i32 - 1 - cursor.y as i32);
+        if x < 0 || y < 0 || x >= window.width as i32 || y >= window.height as i32 {
+            return None;
+        }
+        Picked::at(&self.fb, CLICK_ATTACHMENT, Vector2::new(x, y))
+    }
+}
 
 fn main() {
     let event_loop = EventLoop::with_user_event();
@@ -55,23 +288,47 @@ fn main() {
     let rt = Runtime::new().unwrap();
     rt.spawn(other(event_loop.create_proxy()));
 
-    let images = [
-        image::io::Reader::open(TEST_TILE1)
-            .unwrap()
-            .decode()
-            .unwrap(),
-        image::io::Reader::open(TEST_TILE2)
-            .unwrap()
-            .decode()
-            .unwrap(),
-    ];
-
-    let mut hex_grid = HexGridBuilder::default()
-        .with_dimensions(50, 50)
-        .point_up()
-        .with_tiles(&images)
-        .build();
-    hex_grid.update_tile((2, 1), Some(1));
+    let mut map_path = std::env::args().nth(1);
+    let loaded = match &map_path {
+        // maps imported from Tiled have no document to save back into
+        Some(path) if tiled::is_tiled(path) => {
+            match TiledMap::load(path).and_then(|map| map.build()) {
+                Ok(hex_grid) => {
+                    let (token_manager, _) =
+                        TokenManager::new(hex_grid.layout(), Vec::new()).unwrap();
+                    Some((None, hex_grid, token_manager))
+                }
+                Err(e) => {
+                    println!("Couldn't import {}: {}", path, e);
+                    None
+                }
+            }
+        }
+        Some(path) => {
+            let built = MapDocument::load(path).and_then(|map| {
+                let (hex_grid, token_manager, _) = map.build()?;
+                Ok((Some(map), hex_grid, token_manager))
+            });
+            match built {
+                Ok(loaded) => Some(loaded),
+                Err(e) => {
+                    println!("Couldn't load {}: {}", path, e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let (mut map, mut hex_grid, mut token_manager) = match loaded {
+        Some(loaded) => loaded,
+        None => {
+            // the demo map is saved to the default path, not over a map that didn't load
+            map_path = None;
+            let map = demo_map();
+            let (hex_grid, token_manager, _) = map.build().unwrap();
+            (Some(map), hex_grid, token_manager)
+        }
+    };
 
     let program = fgl::program::ProgramBuilder::default()
         .attach_shader(
@@ -82,6 +339,17 @@ fn main() {
         )
         .link()
         .unwrap();
+    let overlay_program = fgl::program::ProgramBuilder::default()
+        .attach_shader(
+            fgl::program::Shader::from_source(fgl::program::ShaderType::Vertex, OVERLAY_VERT)
+                .unwrap(),
+        )
+        .attach_shader(
+            fgl::program::Shader::from_source(fgl::program::ShaderType::Fragment, OVERLAY_FRAG)
+                .unwrap(),
+        )
+        .link()
+        .unwrap();
 
     let mut projection = cgmath::ortho(
         0f32,
@@ -94,61 +362,32 @@ fn main() {
     let mut scroll = Vector2::zero();
     let mut mouse_position = PhysicalPosition::new(0.0, 0.0);
     let mut drag = false;
+    let mut dragged = false;
+    let mut selected: Option<Selection> = None;
     let mut scale = 0.5f32;
+    let mut sight_from = None;
+    let mut template = None;
+    let mut modifiers = glutin::event::ModifiersState::empty();
+    let mut measuring = false;
+    let mut ruler: Option<Ruler> = None;
+    let mut calibration: Option<Calibration> = None;
+    let mut lines = LineRenderer::new().unwrap();
+    let mut text = TextRenderer::new(48.0).unwrap();
+    let mut outline = GridOutline::new(&hex_grid, OutlineStyle::default()).unwrap();
 
-    let token_image = image::io::Reader::open(TEST_TOKEN)
-        .unwrap()
-        .decode()
-        .unwrap();
-    let (mut token_manager, token_ids) = TokenManager::new(
-        210.0,
-        std::array::IntoIter::new([Token::new(
-            token_image,
-            0,
-            true,
-            Mask::None,
-            CentredOn::Corner { point_up: true },
-        )]),
-    )
-    .unwrap();
-    token_manager.append_instances(&[TokenInstance {
-        coords: (3, 2).into(),
-        token: token_ids[0],
-    }]);
-
-    let mut fb = crate::fgl::framebuffer::FrameBuffer::new();
-    let mut rb = crate::fgl::framebuffer::RenderBuffer::new();
-    rb.alloc(
-        context.window().inner_size().width,
-        context.window().inner_size().height,
-        crate::fgl::framebuffer::Format::DepthStencil,
-        0,
-    );
-    fb.attach_renderbuffer(&rb, crate::fgl::framebuffer::Attachment::DepthStencil);
-    let mut t = fgl::texture::Texture2D::with_dimensions(
-        (context.window().inner_size().width) as i32,
-        (context.window().inner_size().height) as i32,
-        crate::fgl::texture::Format::Rgba,
-    );
-    t.set_min_filter(Filter::Nearest);
-    t.set_mag_filter(Filter::Nearest);
-    fb.attach_texture2d(&t, crate::fgl::framebuffer::Attachment::Color(0));
-    let mut click_t = fgl::texture::Texture2D::with_dimensions(
-        (context.window().inner_size().width) as i32,
-        (context.window().inner_size().height) as i32,
-        crate::fgl::texture::Format::Rgb,
-    );
-    fb.attach_texture2d(&click_t, crate::fgl::framebuffer::Attachment::Color(1));
-    fb.set_draw_buffers(&[Some(0), Some(1)]);          
+    let mut offscreen = Offscreen::new(context.window().inner_size());
     let mut composer = QuadComposer::new(Vector2::new(
         context.window().inner_size().width,
         context.window().inner_size().height,
     ));
 
     event_loop.run(move |event, _, control_flow| unsafe {
-        use glutin::event::{Event, MouseScrollDelta, WindowEvent};
+        use glutin::event::{ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent};
         *control_flow = glutin::event_loop::ControlFlow::Wait;
         match event {
+            Event::NewEvents(glutin::event::StartCause::ResumeTimeReached { .. }) => {
+                context.window().request_redraw();
+            }
             Event::NewEvents(_) => {}
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => {
@@ -159,42 +398,148 @@ fn main() {
                     projection =
                         cgmath::ortho(0f32, ps.width as f32, 0f32, ps.height as f32, -1f32, 100f32);
                     composer.resize(Vector2::new(ps.width, ps.height));
-                    fb = crate::fgl::framebuffer::FrameBuffer::new();
-                    t = fgl::texture::Texture2D::with_dimensions(
-                        ps.width as i32,
-                        ps.height as i32,
-                        crate::fgl::texture::Format::Rgba,
-                    );
-                    t.set_min_filter(Filter::Nearest);
-                    t.set_mag_filter(Filter::Nearest);
-
-                    fb.attach_texture2d(&t, crate::fgl::framebuffer::Attachment::Color(0));
-                    rb = crate::fgl::framebuffer::RenderBuffer::new();
-                    rb.alloc(
-                        ps.width,
-                        ps.height,
-                        crate::fgl::framebuffer::Format::DepthStencil,
-                        0,
-                    );
-                    fb.attach_renderbuffer(&rb, crate::fgl::framebuffer::Attachment::DepthStencil);
-                    let mut click_t = fgl::texture::Texture2D::with_dimensions(
-                        (context.window().inner_size().width) as i32,
-                        (context.window().inner_size().height) as i32,
-                        crate::fgl::texture::Format::Rgb,
-                    );
-                    fb.attach_texture2d(&click_t, crate::fgl::framebuffer::Attachment::Color(1));
-                    fb.set_draw_buffers(&[Some(0), Some(1)]);          
+                    offscreen = Offscreen::new(ps);
                     gl::Viewport(0, 0, ps.width as i32, ps.height as i32);
                 }
+                WindowEvent::MouseInput {
+                    button: winit::event::MouseButton::Left,
+                    state: ElementState::Pressed,
+                    ..
+                } if calibration.is_some() => {
+                    let calibration = calibration.as_mut().unwrap();
+                    if let Some(world) = hex::screen_to_world(
+                        Vector2::new(mouse_position.x as f32, mouse_position.y as f32),
+                        Vector2::new(
+                            context.window().inner_size().width as f32,
+                            context.window().inner_size().height as f32,
+                        ),
+                        view_matrix(projection, scale, scroll),
+                    ) {
+                        calibration.mark(world);
+                    }
+                    lines.clear();
+                    let size = hex_grid.layout().tile_size * 0.1;
+                    for mark in calibration.marks() {
+                        let colour = Vector4::new(1.0, 0.2, 0.2, 0.9);
+                        let (dx, dy) = (Vector2::new(size, 0.0), Vector2::new(0.0, size));
+                        lines.segment(mark - dx, mark + dx, size * 0.3, colour);
+                        lines.segment(mark - dy, mark + dy, size * 0.3, colour);
+                    }
+                    context.window().request_redraw();
+                }
+                WindowEvent::MouseInput {
+                    button: winit::event::MouseButton::Left,
+                    state,
+                    ..
+                } if measuring || ruler.is_some() => {
+                    ruler = if state == ElementState::Pressed {
+                        hex_under_cursor(
+                            &hex_grid,
+                            mouse_position,
+                            context.window().inner_size(),
+                            view_matrix(projection, scale, scroll),
+                        )
+                        .map(Ruler::new)
+                    } else {
+                        None
+                    };
+                    lines.clear();
+                    text.clear();
+                    context.window().request_redraw();
+                }
                 WindowEvent::MouseInput {
                     button: winit::event::MouseButton::Left,
                     state,
                     ..
                 } => {
                     drag = state == winit::event::ElementState::Pressed;
+                    if drag {
+                        dragged = false;
+                    } else if !dragged {
+                        let picked =
+                            offscreen.picked(mouse_position, context.window().inner_size());
+                        let clicked = match picked {
+                            Some(Picked::Hex(coords)) => Some(coords),
+                            Some(Picked::Token(_)) => None,
+                            // cells without tiles aren't in the click buffer
+                            None => hex_under_cursor(
+                                &hex_grid,
+                                mouse_position,
+                                context.window().inner_size(),
+                                view_matrix(projection, scale, scroll),
+                            ),
+                        };
+                        let overlay = hex_grid.overlay_mut();
+                        overlay.channel(MOVEMENT_CHANNEL).clear();
+                        overlay.channel(PATH_CHANNEL).clear();
+                        let handle = match picked {
+                            Some(Picked::Token(handle))
+                                if token_manager.instance(handle).is_some() =>
+                            {
+                                Some(handle)
+                            }
+                            _ => None,
+                        };
+                        selected = match (clicked, handle, selected.take()) {
+                            // clicking the end of the path shown again moves the token there
+                            (Some(coords), None, Some((handle, _, _, Some(target))))
+                                if coords == target =>
+                            {
+                                token_manager.move_instance(handle, coords);
+                                None
+                            }
+                            (_, Some(handle), _) => {
+                                let instance = *token_manager.instance(handle).unwrap();
+                                let (size, centred_on) = token_manager.footprint(&instance);
+                                let range = hex_grid.movement_range(
+                                    instance.coords,
+                                    MOVEMENT_SPEED,
+                                    size,
+                                    centred_on,
+                                    &token_manager.occupied(),
+                                );
+                                range.show(hex_grid.overlay_mut().channel(MOVEMENT_CHANNEL));
+                                Some((handle, instance.coords, range, None))
+                            }
+                            (Some(coords), None, Some((handle, from, range, _))) => {
+                                let overlay = hex_grid.overlay_mut();
+                                range.show(overlay.channel(MOVEMENT_CHANNEL));
+                                let target = range.path_to(coords).map(|path| {
+                                    path.show(overlay.channel(PATH_CHANNEL));
+                                    coords
+                                });
+                                Some((handle, from, range, target))
+                            }
+                            _ => None,
+                        };
+                        context.window().request_redraw();
+                    }
+                }
+                WindowEvent::MouseInput {
+                    button: winit::event::MouseButton::Right,
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                } if ruler.is_some() => {
+                    ruler.as_mut().unwrap().add_waypoint();
+                }
+                WindowEvent::MouseInput {
+                    button: winit::event::MouseButton::Right,
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                } => {
+                    sight_from = hex_under_cursor(
+                        &hex_grid,
+                        mouse_position,
+                        context.window().inner_size(),
+                        view_matrix(projection, scale, scroll),
+                    )
+                    .filter(|coords| sight_from != Some(*coords));
+                    hex_grid.overlay_mut().channel(SIGHT_CHANNEL).clear();
+                    context.window().request_redraw();
                 }
                 WindowEvent::CursorMoved { position, .. } => {
                     if drag {
+                        dragged = true;
                         let scroll_by = Matrix4::from_nonuniform_scale(scale, scale, 1.0)
                             .invert()
                             .unwrap()
@@ -207,8 +552,238 @@ fn main() {
                         scroll += Vector2::new(scroll_by.x, scroll_by.y);
                     }
                     mouse_position = position;
+                    outline.hover(hex_under_cursor(
+                        &hex_grid,
+                        position,
+                        context.window().inner_size(),
+                        view_matrix(projection, scale, scroll),
+                    ));
+                    if let Some(ruler) = &mut ruler {
+                        let hovered = hex_under_cursor(
+                            &hex_grid,
+                            position,
+                            context.window().inner_size(),
+                            view_matrix(projection, scale, scroll),
+                        );
+                        if let Some(coords) = hovered {
+                            ruler.set_end(coords);
+                        }
+                        lines.clear();
+                        text.clear();
+                        ruler.show(hex_grid.layout(), &mut lines, &mut text);
+                    } else if let Some(from) = sight_from {
+                        let hovered = hex_under_cursor(
+                            &hex_grid,
+                            position,
+                            context.window().inner_size(),
+                            view_matrix(projection, scale, scroll),
+                        );
+                        hex_grid.overlay_mut().channel(SIGHT_CHANNEL).clear();
+                        if let Some(to) = hovered {
+                            let sight = hex_grid.line_of_sight(from, to);
+                            sight.show(hex_grid.overlay_mut().channel(SIGHT_CHANNEL));
+                        }
+                    } else if let (Some(template), Some((_, from, _, _))) = (template, &selected) {
+                        let hovered = hex_under_cursor(
+                            &hex_grid,
+                            position,
+                            context.window().inner_size(),
+                            view_matrix(projection, scale, scroll),
+                        );
+                        hex_grid.overlay_mut().channel(TEMPLATE_CHANNEL).clear();
+                        if let Some(aim) = hovered {
+                            let area = hex_grid.template_area(template, *from, aim);
+                            let overlay = hex_grid.overlay_mut();
+                            Template::show(&area, overlay.channel(TEMPLATE_CHANNEL));
+                        }
+                    }
+                    context.window().request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(MEASURE_KEY),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    measuring = state == ElementState::Pressed;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(CALIBRATE_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    calibration = match calibration.take() {
+                        Some(calibration) => {
+                            if let Some(layout) = calibration.layout() {
+                                hex_grid.set_layout(layout);
+                                hex_grid.cover_background();
+                                token_manager.set_layout(layout);
+                                outline.rebuild(&hex_grid);
+                            }
+                            None
+                        }
+                        None if hex_grid.background().is_some() => {
+                            if hex_grid.layout().shape == Shape::Square {
+                                Some(Calibration::square())
+                            } else {
+                                Some(Calibration::hex())
+                            }
+                        }
+                        None => None,
+                    };
+                    lines.clear();
                     context.window().request_redraw();
                 }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(SAVE_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if let Some(map) = &mut map {
+                        map.sync(&hex_grid, &token_manager);
+                        let path = map_path.as_deref().unwrap_or(DEFAULT_SAVE);
+                        if let Err(e) = map.save(path) {
+                            println!("Couldn't save {}: {}", path, e);
+                        }
+                    } else {
+                        println!("Maps imported from Tiled can't be saved yet");
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(REMOVE_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } if selected.is_some() => {
+                    let (handle, ..) = selected.take().unwrap();
+                    token_manager.remove_instance(handle);
+                    let overlay = hex_grid.overlay_mut();
+                    overlay.channel(MOVEMENT_CHANNEL).clear();
+                    overlay.channel(PATH_CHANNEL).clear();
+                    context.window().request_redraw();
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode:
+                                Some(
+                                    key @ (VirtualKeyCode::Left
+                                    | VirtualKeyCode::Right
+                                    | VirtualKeyCode::Up
+                                    | VirtualKeyCode::Down),
+                                ),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let edge = match key {
+                        VirtualKeyCode::Left => Edge::Left,
+                        VirtualKeyCode::Right => Edge::Right,
+                        VirtualKeyCode::Up => Edge::Top,
+                        _ => Edge::Bottom,
+                    };
+                    let grow = !modifiers.shift();
+                    match resize(&mut hex_grid, &mut token_manager, edge, grow) {
+                        Ok(()) => {
+                            selected = None;
+                            sight_from = None;
+                            let overlay = hex_grid.overlay_mut();
+                            for channel in &[MOVEMENT_CHANNEL, PATH_CHANNEL, SIGHT_CHANNEL] {
+                                overlay.channel(channel).clear();
+                            }
+                            outline.rebuild(&hex_grid);
+                            context.window().request_redraw();
+                        }
+                        Err(e) => println!("Couldn't resize the map: {}", e),
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(EXPORT_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let mut style = outline.style();
+                    if !outline.labels_visible {
+                        style.labels = None;
+                    }
+                    let options = ExportOptions {
+                        grid_lines: outline.visible.then_some(style),
+                        ..ExportOptions::default()
+                    };
+                    let exported = export::export_png(
+                        EXPORT_PATH,
+                        &hex_grid,
+                        &token_manager,
+                        &program,
+                        &options,
+                    );
+                    if let Err(e) = exported {
+                        println!("Couldn't export {}: {}", EXPORT_PATH, e);
+                    }
+                    context.window().request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(OUTLINE_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    outline.visible = !outline.visible;
+                    context.window().request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(LABELS_KEY),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    outline.labels_visible = !outline.labels_visible;
+                    context.window().request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    template = match key {
+                        VirtualKeyCode::Key1 => Some(Template::Blast(1)),
+                        VirtualKeyCode::Key2 => Some(Template::Burst(2)),
+                        VirtualKeyCode::Key3 => Some(Template::Cone(3)),
+                        VirtualKeyCode::Key4 => Some(Template::Line(5)),
+                        VirtualKeyCode::Escape => None,
+                        _ => template,
+                    };
+                }
                 WindowEvent::MouseWheel { delta, .. } => {
                     match delta {
                         MouseScrollDelta::LineDelta(_, y) => {
@@ -243,22 +818,26 @@ fn main() {
             Event::RedrawRequested(_) => {
                 gl::ClearColor(0.9, 0.9, 0.9, 1.0);
                 gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                let fb = &offscreen.fb;
                 fb.clear_color(0, &[0u32, 0, 0, 0]);
-                fb.clear_color(1, &[0u32, 125, 0]);
+                fb.clear_color(CLICK_ATTACHMENT as i32, &[0u32, 0, 0, 0]);
 
                 fb.bind();
-                hex_grid.draw(
-                    &program,
-                    projection
-                        * cgmath::Matrix4::from_nonuniform_scale(scale, scale, 1.0)
-                        * cgmath::Matrix4::from_translation(Vector3::new(scroll.x, scroll.y, 0f32)),
-                );
-                fb.bind();
-                token_manager.draw(
-                    projection
-                        * cgmath::Matrix4::from_nonuniform_scale(scale, scale, 1.0)
-                        * cgmath::Matrix4::from_translation(Vector3::new(scroll.x, scroll.y, 0f32)),
-                );
+                hex_grid.draw(&program, view_matrix(projection, scale, scroll));
+                // only the grid and tokens can be clicked, so nothing else
+                // drawn may touch the click buffer
+                fb.set_draw_buffers(&[Some(0), None]);
+                outline.draw(view_matrix(projection, scale, scroll));
+                hex_grid.update_overlay();
+                hex_grid.draw_overlay(&overlay_program, view_matrix(projection, scale, scroll));
+                fb.set_draw_buffers(&[Some(0), Some(CLICK_ATTACHMENT)]);
+                token_manager.draw(view_matrix(projection, scale, scroll));
+                fb.set_draw_buffers(&[Some(0), None]);
+                lines.update();
+                lines.draw(view_matrix(projection, scale, scroll));
+                text.update();
+                text.draw(view_matrix(projection, scale, scroll));
+                fb.set_draw_buffers(&[Some(0), Some(CLICK_ATTACHMENT)]);
                 fb.unbind();
                 composer.render_quad(0, Quad {
                     offset: Zero::zero(),
@@ -266,16 +845,7 @@ fn main() {
                         context.window().inner_size().width,
                         context.window().inner_size().height,
                     )
-                }, &t);
-                composer.render_quad(1, Quad {
-                    offset: Vector2::new(
-                        10, 10
-                    ),
-                    size: Vector2::new(
-                        (context.window().inner_size().width as f64 * 0.1) as u32,
-                        (context.window().inner_size().height as f64 * 0.1) as u32,
-                    )
-                }, &click_t);
+                }, &offscreen.colour);
 
                 composer.end_frame();
                 let mut err = gl::GetError();
@@ -293,7 +863,13 @@ fn main() {
                 }
                 context.swap_buffers().unwrap();
             }
-            Event::RedrawEventsCleared => {}
+            Event::RedrawEventsCleared => {
+                if hex_grid.animated() {
+                    *control_flow = glutin::event_loop::ControlFlow::WaitUntil(
+                        std::time::Instant::now() + ANIMATION_FRAME,
+                    );
+                }
+            }
             Event::LoopDestroyed => {}
         }
     });
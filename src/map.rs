@@ -0,0 +1,657 @@
+use crate::hex::background::Background;
+use crate::hex::grid::{GridError, HexGrid, HexGridBuilder, Terrain, BASE_LAYER};
+use crate::hex::layer::Orientation;
+use crate::hex::layout::{Layout, Shape};
+use crate::hex::tile::Tile;
+use crate::hex::token::{CentredOn, Mask, Token, TokenHandle, TokenInstance, TokenManager};
+use cgmath::Vector2;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Version written into new files, bumped whenever the format changes
+pub const VERSION: u32 = 1;
+
+/// Everything needed to rebuild a map, in a form that can be written to disk
+///
+/// Images are referenced by path rather than embedded, relative to the
+/// working directory. Per-hex data is stored row by row like the grid does.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapDocument {
+    pub version: u32,
+    pub metadata: Metadata,
+    pub grid: GridDocument,
+    pub tilesets: Vec<Tileset>,
+    /// Bottom to top, one of them called `BASE_LAYER`
+    pub layers: Vec<LayerDocument>,
+    pub background: Option<String>,
+    pub tokens: Vec<TokenDocument>,
+    pub instances: Vec<InstanceDocument>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GridDocument {
+    pub shape: Shape,
+    pub tile_size: f32,
+    pub origin: (f32, f32),
+    pub width: u32,
+    pub height: u32,
+    pub terrain: Vec<Terrain>,
+    pub elevation: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tileset {
+    pub name: String,
+    /// Image paths, in tile index order, animated GIFs and PNGs keeping their frames
+    pub tiles: Vec<String>,
+    /// Tiles whose image is a strip of frames laid out left to right, by
+    /// index, with how many milliseconds each frame is shown for
+    #[serde(default)]
+    pub strips: BTreeMap<usize, Vec<u32>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerDocument {
+    pub name: String,
+    /// Index into `MapDocument::tilesets`
+    pub tileset: usize,
+    pub contents: Vec<Option<usize>>,
+    pub orientations: Vec<Orientation>,
+    pub visible: bool,
+    pub opacity: f32,
+    #[serde(default)]
+    pub gm_only: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenDocument {
+    pub image: String,
+    pub nominal_size: u32,
+    pub scale: bool,
+    pub mask: Mask,
+    pub centred_on: CentredOn,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstanceDocument {
+    /// Index into `MapDocument::tokens`
+    pub token: usize,
+    pub coords: (u32, u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    MessagePack,
+    /// Rusty Object Notation, for files meant to be read and edited by hand
+    Ron,
+}
+
+impl Format {
+    /// RON for `.ron` files, MessagePack for anything else
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Format::Ron,
+            _ => Format::MessagePack,
+        }
+    }
+}
+
+/// Just enough of a document to tell which version it is
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl MapDocument {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(MapError::Io)?;
+        Self::from_bytes(&bytes, Format::from_path(path))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        let path = path.as_ref();
+        let bytes = self.to_bytes(Format::from_path(path))?;
+        std::fs::write(path, bytes).map_err(MapError::Io)
+    }
+
+    pub fn to_bytes(&self, format: Format) -> Result<Vec<u8>, MapError> {
+        match format {
+            // named fields, so later versions can still read the header
+            Format::MessagePack => {
+                rmp_serde::to_vec_named(self).map_err(|e| MapError::Encode(e.to_string()))
+            }
+            Format::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map(String::into_bytes)
+                .map_err(|e| MapError::Encode(e.to_string())),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8], format: Format) -> Result<Self, MapError> {
+        let header: Header = decode(bytes, format)?;
+        match header.version {
+            // older versions get migrated here as the format changes
+            VERSION => decode(bytes, format),
+            version => Err(MapError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Loads every image the document refers to and builds the grid and tokens from it
+    ///
+    /// The handles are in the same order as `tokens`.
+    pub fn build(&self) -> Result<(HexGrid, TokenManager, Vec<TokenHandle>), MapError> {
+        self.check_cells()?;
+        self.check_references()?;
+        let tilesets = self
+            .tilesets
+            .iter()
+            .map(|tileset| {
+                let tiles = tileset.tiles.iter().enumerate();
+                tiles
+                    .map(|(idx, path)| match tileset.strips.get(&idx) {
+                        Some(durations) => open_strip(path, durations),
+                        None => open_tile(path),
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        let base = self
+            .layers
+            .iter()
+            .position(|layer| layer.name == BASE_LAYER)
+            .ok_or_else(|| MapError::Grid(GridError::NoSuchLayer(BASE_LAYER.to_string())))?;
+        let tiles = |layer: &LayerDocument| {
+            tilesets
+                .get(layer.tileset)
+                .map(Vec::as_slice)
+                .ok_or(MapError::NoSuchTileset(layer.tileset))
+        };
+
+        let grid = &self.grid;
+        let mut builder = HexGridBuilder::default()
+            .with_dimensions(grid.width, grid.height)
+            .with_tile_size(grid.tile_size as u32)
+            .with_tiles(tiles(&self.layers[base])?)
+            .with_grid_contents(&self.layers[base].contents)
+            .with_orientations(&self.layers[base].orientations);
+        builder = match grid.shape {
+            Shape::Hex { point_up: true } => builder.point_up(),
+            Shape::Hex { point_up: false } => builder,
+            Shape::Square => builder.square(),
+            Shape::Gridless => builder.gridless(),
+        };
+        for layer in self.layers.iter().filter(|layer| layer.name != BASE_LAYER) {
+            builder = builder.with_layer(&layer.name, tiles(layer)?);
+        }
+        let mut hex_grid = builder.build();
+        let layout = Layout::new(grid.shape, grid.tile_size)
+            .with_origin(Vector2::new(grid.origin.0, grid.origin.1));
+        hex_grid.set_layout(layout);
+
+        for (position, layer) in self.layers.iter().enumerate() {
+            if layer.name != BASE_LAYER {
+                let cells = layer.contents.iter().zip(&layer.orientations);
+                let tiles = cells.enumerate().filter_map(|(idx, (tile, orientation))| {
+                    let coords = Vector2::new(idx as u32 % grid.width, idx as u32 / grid.width);
+                    tile.map(|tile| (coords, Some(tile), *orientation))
+                });
+                hex_grid
+                    .set_tiles(&layer.name, tiles)
+                    .map_err(MapError::Grid)?;
+            }
+            if hex_grid.layers().position(|l| l.name() == layer.name) != Some(position) {
+                hex_grid
+                    .move_layer(&layer.name, position)
+                    .map_err(MapError::Grid)?;
+            }
+            let tile_layer = hex_grid.layer_mut(&layer.name).unwrap();
+            tile_layer.visible = layer.visible;
+            tile_layer.opacity = layer.opacity;
+            tile_layer.gm_only = layer.gm_only;
+        }
+        let coords = |idx: usize| Vector2::new(idx as u32 % grid.width, idx as u32 / grid.width);
+        for (idx, terrain) in grid.terrain.iter().enumerate() {
+            hex_grid
+                .set_terrain(coords(idx), *terrain)
+                .map_err(MapError::Grid)?;
+        }
+        let elevations = grid.elevation.iter().enumerate();
+        hex_grid
+            .set_elevations(
+                elevations
+                    .filter(|(_, elevation)| **elevation != 0)
+                    .map(|(idx, elevation)| (coords(idx), *elevation)),
+            )
+            .map_err(MapError::Grid)?;
+        if let Some(path) = &self.background {
+            let background = Background::new(open(path)?).map_err(MapError::Gl)?;
+            hex_grid.set_background(Some(background));
+        }
+
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|token| {
+                Ok(Token::new(
+                    open(&token.image)?,
+                    token.nominal_size,
+                    token.scale,
+                    token.mask,
+                    token.centred_on,
+                ))
+            })
+            .collect::<Result<Vec<_>, MapError>>()?;
+        let (mut token_manager, handles) =
+            TokenManager::new(hex_grid.layout(), tokens).map_err(MapError::Gl)?;
+        let instances = self
+            .instances
+            .iter()
+            .map(|instance| {
+                let token = *handles
+                    .get(instance.token)
+                    .ok_or(MapError::NoSuchToken(instance.token))?;
+                Ok(TokenInstance {
+                    coords: instance.coords.into(),
+                    token,
+                })
+            })
+            .collect::<Result<Vec<_>, MapError>>()?;
+        if !instances.is_empty() {
+            token_manager.append_instances(&instances);
+        }
+
+        Ok((hex_grid, token_manager, handles))
+    }
+
+    /// Makes sure layers, tiles and token instances only refer to things
+    /// that exist, and that no two layers share a name
+    fn check_references(&self) -> Result<(), MapError> {
+        for (n, layer) in self.layers.iter().enumerate() {
+            if self.layers[..n]
+                .iter()
+                .any(|other| other.name == layer.name)
+            {
+                return Err(MapError::DuplicateLayer(layer.name.clone()));
+            }
+            let tileset = self
+                .tilesets
+                .get(layer.tileset)
+                .ok_or(MapError::NoSuchTileset(layer.tileset))?;
+            if let Some(tile) = layer
+                .contents
+                .iter()
+                .flatten()
+                .find(|&&tile| tile >= tileset.tiles.len())
+            {
+                return Err(MapError::NoSuchTile(layer.name.clone(), *tile));
+            }
+        }
+        for instance in &self.instances {
+            if instance.token >= self.tokens.len() {
+                return Err(MapError::NoSuchToken(instance.token));
+            }
+            let (x, y) = instance.coords;
+            if x >= self.grid.width || y >= self.grid.height {
+                return Err(MapError::OffGrid(x, y));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure all per-hex data has one entry for every hex of the grid
+    fn check_cells(&self) -> Result<(), MapError> {
+        let grid = &self.grid;
+        let cells = match grid.width.checked_mul(grid.height) {
+            Some(cells) if cells > 0 => cells as usize,
+            _ => return Err(MapError::BadDimensions(grid.width, grid.height)),
+        };
+        let check = |what: &str, len: usize| {
+            if len == cells {
+                Ok(())
+            } else {
+                Err(MapError::WrongCellCount(what.to_string(), len, cells))
+            }
+        };
+        check("terrain", grid.terrain.len())?;
+        check("elevation", grid.elevation.len())?;
+        for layer in &self.layers {
+            check(&format!("layer {}", layer.name), layer.contents.len())?;
+            check(
+                &format!("orientations of layer {}", layer.name),
+                layer.orientations.len(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Copies everything that can change while playing back into the document
+    ///
+    /// Tilesets, token art and the background are left as they are, and so
+    /// are layers and tokens the document doesn't know about yet.
+    pub fn sync(&mut self, hex_grid: &HexGrid, token_manager: &TokenManager) {
+        let layout = hex_grid.layout();
+        let (width, height) = hex_grid.dimensions();
+        let cells = || (0..height).flat_map(move |y| (0..width).map(move |x| Vector2::new(x, y)));
+
+        self.grid = GridDocument {
+            shape: layout.shape,
+            tile_size: layout.tile_size,
+            origin: (layout.origin.x, layout.origin.y),
+            width,
+            height,
+            terrain: cells()
+                .map(|coords| hex_grid.terrain(coords).unwrap())
+                .collect(),
+            elevation: cells()
+                .map(|coords| hex_grid.elevation(coords).unwrap())
+                .collect(),
+        };
+
+        let mut layers = Vec::new();
+        for tile_layer in hex_grid.layers() {
+            let mut layer = match self
+                .layers
+                .iter()
+                .find(|layer| layer.name == tile_layer.name())
+            {
+                Some(layer) => layer.clone(),
+                None => continue,
+            };
+            let tiles: Vec<_> = cells()
+                .map(|coords| hex_grid.tile(tile_layer.name(), coords).unwrap())
+                .collect();
+            layer.contents = tiles
+                .iter()
+                .map(|tile| tile.map(|(tile, _)| tile))
+                .collect();
+            layer.orientations = tiles
+                .iter()
+                .map(|tile| tile.map(|(_, orientation)| orientation).unwrap_or_default())
+                .collect();
+            layer.visible = tile_layer.visible;
+            layer.opacity = tile_layer.opacity;
+            layer.gm_only = tile_layer.gm_only;
+            layers.push(layer);
+        }
+        self.layers = layers;
+
+        let known = self.tokens.len();
+        self.instances = token_manager
+            .instances()
+            .iter()
+            .filter(|instance| instance.token.index() < known)
+            .map(|instance| InstanceDocument {
+                token: instance.token.index(),
+                coords: (instance.coords.x, instance.coords.y),
+            })
+            .collect();
+    }
+}
+
+fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8], format: Format) -> Result<T, MapError> {
+    match format {
+        Format::MessagePack => {
+            rmp_serde::from_slice(bytes).map_err(|e| MapError::Decode(e.to_string()))
+        }
+        Format::Ron => std::str::from_utf8(bytes)
+            .map_err(|e| MapError::Decode(e.to_string()))
+            .and_then(|text| ron::from_str(text).map_err(|e| MapError::Decode(e.to_string()))),
+    }
+}
+
+fn open(path: &str) -> Result<DynamicImage, MapError> {
+    image::open(path).map_err(|e| MapError::Image(path.to_string(), e))
+}
+
+fn open_tile(path: &str) -> Result<Tile, MapError> {
+    Tile::open(path).map_err(|e| MapError::Image(path.to_string(), e))
+}
+
+fn open_strip(path: &str, durations: &[u32]) -> Result<Tile, MapError> {
+    let durations: Vec<_> = durations
+        .iter()
+        .map(|&ms| Duration::from_millis(u64::from(ms)))
+        .collect();
+    Tile::from_strip(&open(path)?, &durations)
+        .ok_or_else(|| MapError::BadStrip(path.to_string(), durations.len()))
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Encode(String),
+    Decode(String),
+    /// Written by a version this one can't read, most likely a newer one
+    UnsupportedVersion(u32),
+    Image(String, image::ImageError),
+    NoSuchTileset(usize),
+    NoSuchToken(usize),
+    /// A layer using a tile its tileset doesn't have
+    NoSuchTile(String, usize),
+    DuplicateLayer(String),
+    /// A token instance placed outside the grid
+    OffGrid(u32, u32),
+    /// A sprite strip that can't be cut into this many frames of the same width
+    BadStrip(String, usize),
+    /// The grid is empty, or too large to address
+    BadDimensions(u32, u32),
+    /// Per-hex data with the wrong number of entries, what it is, how many
+    /// entries it has and how many hexes there are
+    WrongCellCount(String, usize, usize),
+    Grid(GridError),
+    /// Setting up buffers or shaders failed
+    Gl(String),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "{}", e),
+            MapError::Encode(e) => write!(f, "couldn't encode the map: {}", e),
+            MapError::Decode(e) => write!(f, "couldn't decode the map: {}", e),
+            MapError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "can't read map version {}, only up to {}",
+                    version, VERSION
+                )
+            }
+            MapError::Image(path, e) => write!(f, "couldn't load {}: {}", path, e),
+            MapError::NoSuchTileset(idx) => write!(f, "there is no tileset {}", idx),
+            MapError::NoSuchToken(idx) => write!(f, "there is no token {}", idx),
+            MapError::NoSuchTile(layer, idx) => {
+                write!(
+                    f,
+                    "layer {} uses tile {}, which its tileset lacks",
+                    layer, idx
+                )
+            }
+            MapError::DuplicateLayer(name) => write!(f, "there are two layers called {}", name),
+            MapError::OffGrid(x, y) => write!(f, "a token is placed off the grid at {}, {}", x, y),
+            MapError::BadStrip(path, frames) => {
+                write!(f, "{} can't be cut into {} frames", path, frames)
+            }
+            MapError::BadDimensions(width, height) => {
+                write!(f, "a grid can't be {}x{} hexes", width, height)
+            }
+            MapError::WrongCellCount(what, len, cells) => {
+                write!(f, "{} has {} entries for {} hexes", what, len, cells)
+            }
+            MapError::Grid(e) => write!(f, "{}", e),
+            MapError::Gl(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> MapDocument {
+        let (width, height) = (3, 2);
+        let cells = (width * height) as usize;
+        let mut terrain = vec![Terrain::default(); cells];
+        terrain[4] = Terrain {
+            blocks_sight: true,
+            movement_cost: None,
+        };
+        MapDocument {
+            version: VERSION,
+            metadata: Metadata {
+                name: "Test".to_string(),
+                ..Metadata::default()
+            },
+            grid: GridDocument {
+                shape: Shape::Hex { point_up: true },
+                tile_size: 64.0,
+                origin: (-12.5, 3.0),
+                width,
+                height,
+                terrain,
+                elevation: vec![0, 1, 2, -1, 0, 3],
+            },
+            tilesets: vec![Tileset {
+                name: "Base".to_string(),
+                tiles: vec!["a.png".to_string(), "b.png".to_string()],
+                strips: vec![(1, vec![100, 250])].into_iter().collect(),
+            }],
+            layers: vec![LayerDocument {
+                name: BASE_LAYER.to_string(),
+                tileset: 0,
+                contents: vec![Some(0), None, Some(1), Some(1), None, Some(0)],
+                orientations: vec![
+                    Orientation {
+                        rotation: 2,
+                        mirrored: true,
+                    };
+                    cells
+                ],
+                visible: true,
+                opacity: 0.5,
+                gm_only: false,
+            }],
+            background: Some("map.png".to_string()),
+            tokens: vec![TokenDocument {
+                image: "token.png".to_string(),
+                nominal_size: 2,
+                scale: true,
+                mask: Mask::Clip,
+                centred_on: CentredOn::Corner { point_up: true },
+            }],
+            instances: vec![InstanceDocument {
+                token: 0,
+                coords: (1, 1),
+            }],
+        }
+    }
+
+    #[test]
+    fn documents_survive_saving_and_loading() {
+        for &format in &[Format::MessagePack, Format::Ron] {
+            let bytes = document().to_bytes(format).unwrap();
+            assert_eq!(MapDocument::from_bytes(&bytes, format).unwrap(), document());
+        }
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        for &format in &[Format::MessagePack, Format::Ron] {
+            let mut newer = document();
+            newer.version = VERSION + 1;
+            let bytes = newer.to_bytes(format).unwrap();
+            assert!(matches!(
+                MapDocument::from_bytes(&bytes, format),
+                Err(MapError::UnsupportedVersion(version)) if version == VERSION + 1
+            ));
+        }
+    }
+
+    #[test]
+    fn formats_follow_the_extension() {
+        assert_eq!(Format::from_path(Path::new("map.ron")), Format::Ron);
+        assert_eq!(Format::from_path(Path::new("map.vtt")), Format::MessagePack);
+    }
+
+    #[test]
+    fn per_hex_data_has_to_cover_the_grid() {
+        assert!(document().check_cells().is_ok());
+
+        let mut short = document();
+        short.grid.elevation.pop();
+        assert!(matches!(
+            short.check_cells(),
+            Err(MapError::WrongCellCount(_, 5, 6))
+        ));
+
+        let mut long = document();
+        long.layers[0].orientations.push(Orientation::default());
+        assert!(matches!(
+            long.check_cells(),
+            Err(MapError::WrongCellCount(_, 7, 6))
+        ));
+
+        let mut empty = document();
+        empty.grid.width = 0;
+        assert!(matches!(
+            empty.check_cells(),
+            Err(MapError::BadDimensions(0, 2))
+        ));
+    }
+
+    #[test]
+    fn tiles_have_to_be_in_the_tileset() {
+        assert!(document().check_references().is_ok());
+        let mut missing = document();
+        missing.layers[0].contents[1] = Some(2);
+        assert!(matches!(
+            missing.check_references(),
+            Err(MapError::NoSuchTile(_, 2))
+        ));
+        let mut no_tileset = document();
+        no_tileset.layers[0].tileset = 1;
+        assert!(matches!(
+            no_tileset.check_references(),
+            Err(MapError::NoSuchTileset(1))
+        ));
+    }
+
+    #[test]
+    fn layer_names_have_to_be_unique() {
+        let mut twice = document();
+        let mut copy = twice.layers[0].clone();
+        copy.contents = vec![None; 6];
+        twice.layers.push(copy);
+        assert!(matches!(
+            twice.check_references(),
+            Err(MapError::DuplicateLayer(name)) if name == BASE_LAYER
+        ));
+    }
+
+    #[test]
+    fn instances_have_to_be_on_the_grid() {
+        let mut off_grid = document();
+        off_grid.instances[0].coords = (3, 0);
+        assert!(matches!(
+            off_grid.check_references(),
+            Err(MapError::OffGrid(3, 0))
+        ));
+        let mut no_token = document();
+        no_token.instances[0].token = 1;
+        assert!(matches!(
+            no_token.check_references(),
+            Err(MapError::NoSuchToken(1))
+        ));
+    }
+}
@@ -1 +1,3 @@
-pub mod compose;
\ No newline at end of file
+pub mod compose;
+pub mod line;
+pub mod text;
@@ -0,0 +1,93 @@
+use crate::fgl::{self, Bindable, Program, ProgramBuilder, Shader};
+use cgmath::{Vector2, Vector4};
+
+const VERT: &str = include_str!("../../resources/shaders/line.vert");
+const FRAG: &str = include_str!("../../resources/shaders/line.frag");
+
+/// Straight, flat coloured segments in world space
+pub struct LineRenderer {
+    ends: Vec<Vector4<f32>>,
+    colours: Vec<Vector4<f32>>,
+    widths: Vec<f32>,
+    vbos: [fgl::VertexBuffer; 4],
+    vao: fgl::VertexAttribObject,
+    needs_update: bool,
+    program: Program,
+}
+
+impl LineRenderer {
+    pub fn new() -> Result<Self, String> {
+        let vao = fgl::VertexAttribObject::new();
+        let mut vbos: [fgl::VertexBuffer; 4] = fgl::VertexBuffer::new_array();
+
+        vbos[0].alloc_with(
+            &fgl::consts::QUAD,
+            fgl::AccessFrequency::Static,
+            fgl::AccessType::Draw,
+        );
+        vao.vertex_attribute_array(
+            &vbos[0],
+            fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
+        );
+        for (id, components) in [(1, 4), (2, 4), (3, 1)].iter().copied() {
+            vao.vertex_attribute_array(
+                &vbos[id as usize],
+                fgl::VertexAttribArray::<f32>::with_id(id)
+                    .with_components_per_value(components)
+                    .with_divisor(1),
+            );
+        }
+
+        let program = ProgramBuilder::default()
+            .attach_shader(Shader::from_source(fgl::ShaderType::Fragment, FRAG)?)
+            .attach_shader(Shader::from_source(fgl::ShaderType::Vertex, VERT)?)
+            .link()?;
+
+        Ok(Self {
+            ends: Vec::new(),
+            colours: Vec::new(),
+            widths: Vec::new(),
+            vbos,
+            vao,
+            needs_update: false,
+            program,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.ends.clear();
+        self.colours.clear();
+        self.widths.clear();
+        self.needs_update = true;
+    }
+
+    /// Queues a segment from `a` to `b`, `width` world units wide
+    pub fn segment(&mut self, a: Vector2<f32>, b: Vector2<f32>, width: f32, colour: Vector4<f32>) {
+        self.ends.push(Vector4::new(a.x, a.y, b.x, b.y));
+        self.colours.push(colour);
+        self.widths.push(width);
+        self.needs_update = true;
+    }
+
+    pub fn update(&mut self) {
+        if self.needs_update && !self.ends.is_empty() {
+            let (freq, typ) = (fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+            self.vbos[1].alloc_with(&self.ends, freq, typ);
+            self.vbos[2].alloc_with(&self.colours, freq, typ);
+            self.vbos[3].alloc_with(&self.widths, freq, typ);
+        }
+        self.needs_update = false;
+    }
+
+    pub fn draw(&self, projection: cgmath::Matrix4<f32>) {
+        if self.ends.is_empty() {
+            return;
+        }
+        self.vao.bind();
+        self.program.bind();
+        self.program.uniform_mat4("projection", &projection);
+        unsafe {
+            gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, self.ends.len() as i32);
+        }
+    }
+}
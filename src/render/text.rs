@@ -0,0 +1,264 @@
+use crate::fgl::{self, texture::Texture2D, Bindable, Program, ProgramBuilder, Shader};
+use cgmath::{Vector2, Vector4};
+use font_kit::canvas::{self, Canvas, RasterizationOptions};
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::hinting::HintingOptions;
+use font_kit::properties::Properties;
+use font_kit::source::SystemSource;
+use image::{DynamicImage, Rgba, RgbaImage};
+use pathfinder_geometry::transform2d::Transform2F;
+use std::collections::HashMap;
+
+const VERT: &str = include_str!("../../resources/shaders/text.vert");
+const FRAG: &str = include_str!("../../resources/shaders/text.frag");
+
+const ATLAS_SIZE: u32 = 512;
+/// Gap left between glyphs in the atlas so filtering doesn't bleed
+const PADDING: u32 = 2;
+
+/// Where a rasterised glyph lives in the atlas, and how to place it
+///
+/// Sizes are in atlas pixels, `bearing` is the offset of the bitmap's bottom
+/// left corner from the pen position on the baseline.
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    origin: Vector2<u32>,
+    size: Vector2<u32>,
+    bearing: Vector2<f32>,
+    advance: f32,
+}
+
+/// Single line text labels in world space
+///
+/// Glyphs are rasterised on first use into a shared atlas, so every label is
+/// drawn in one call.
+pub struct TextRenderer {
+    font: Font,
+    pixel_size: f32,
+    atlas: Texture2D,
+    glyphs: HashMap<char, Option<Glyph>>,
+    /// Next free position on the current shelf, and the shelf's height
+    shelf: (Vector2<u32>, u32),
+    rects: Vec<Vector4<f32>>,
+    uvs: Vec<Vector4<f32>>,
+    colours: Vec<Vector4<f32>>,
+    vbos: [fgl::VertexBuffer; 4],
+    vao: fgl::VertexAttribObject,
+    needs_update: bool,
+    program: Program,
+}
+
+impl TextRenderer {
+    /// Uses the system's default sans-serif font, rasterised at `pixel_size`
+    pub fn new(pixel_size: f32) -> Result<Self, String> {
+        let font = SystemSource::new()
+            .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+            .map_err(|e| e.to_string())?
+            .load()
+            .map_err(|e| e.to_string())?;
+
+        let atlas = Texture2D::with_dimensions(
+            ATLAS_SIZE as i32,
+            ATLAS_SIZE as i32,
+            fgl::texture::Format::Rgba,
+        );
+        atlas.set_min_filter(fgl::texture::Filter::Linear);
+        atlas.set_mag_filter(fgl::texture::Filter::Linear);
+
+        let vao = fgl::VertexAttribObject::new();
+        let mut vbos: [fgl::VertexBuffer; 4] = fgl::VertexBuffer::new_array();
+        vbos[0].alloc_with(
+            &fgl::consts::QUAD,
+            fgl::AccessFrequency::Static,
+            fgl::AccessType::Draw,
+        );
+        vao.vertex_attribute_array(
+            &vbos[0],
+            fgl::VertexAttribArray::<f32>::with_id(0).with_components_per_value(2),
+        );
+        for id in 1..4 {
+            vao.vertex_attribute_array(
+                &vbos[id as usize],
+                fgl::VertexAttribArray::<f32>::with_id(id)
+                    .with_components_per_value(4)
+                    .with_divisor(1),
+            );
+        }
+
+        let program = ProgramBuilder::default()
+            .attach_shader(Shader::from_source(fgl::ShaderType::Fragment, FRAG)?)
+            .attach_shader(Shader::from_source(fgl::ShaderType::Vertex, VERT)?)
+            .link()?;
+
+        Ok(Self {
+            font,
+            pixel_size,
+            atlas,
+            glyphs: HashMap::new(),
+            shelf: (Vector2::new(0, 0), 0),
+            rects: Vec::new(),
+            uvs: Vec::new(),
+            colours: Vec::new(),
+            vbos,
+            vao,
+            needs_update: false,
+            program,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.uvs.clear();
+        self.colours.clear();
+        self.needs_update = true;
+    }
+
+    /// Width of `text` in world units when drawn `height` units tall
+    pub fn width(&mut self, text: &str, height: f32) -> f32 {
+        let scale = height / self.pixel_size;
+        text.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|glyph| glyph.advance * scale)
+            .sum()
+    }
+
+    /// Queues `text` centred on `at`, with an em size of `height` world units
+    pub fn label(&mut self, text: &str, at: Vector2<f32>, height: f32, colour: Vector4<f32>) {
+        let scale = height / self.pixel_size;
+        let width = self.width(text, height);
+        // roughly centres digits and capitals vertically
+        let mut pen = Vector2::new(at.x - width / 2.0, at.y - height * 0.35);
+        for c in text.chars() {
+            let glyph = match self.glyph(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            if glyph.size.x > 0 && glyph.size.y > 0 {
+                let corner = pen + glyph.bearing * scale;
+                let size = glyph.size.map(|x| x as f32);
+                self.rects.push(Vector4::new(
+                    corner.x,
+                    corner.y,
+                    size.x * scale,
+                    size.y * scale,
+                ));
+                let origin = glyph.origin.map(|x| x as f32) / ATLAS_SIZE as f32;
+                let size = size / ATLAS_SIZE as f32;
+                self.uvs
+                    .push(Vector4::new(origin.x, origin.y, size.x, size.y));
+                self.colours.push(colour);
+            }
+            pen.x += glyph.advance * scale;
+        }
+        self.needs_update = true;
+    }
+
+    fn glyph(&mut self, c: char) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&c) {
+            return *glyph;
+        }
+        let glyph = self.rasterise(c);
+        self.glyphs.insert(c, glyph);
+        glyph
+    }
+
+    /// Rasterises `c` into the atlas, `None` if the font lacks it or the atlas is full
+    fn rasterise(&mut self, c: char) -> Option<Glyph> {
+        let id = self.font.glyph_for_char(c)?;
+        let units_per_em = self.font.metrics().units_per_em as f32;
+        let advance = self.font.advance(id).ok()?.x() * self.pixel_size / units_per_em;
+        let (hinting, rasterisation) = (HintingOptions::None, RasterizationOptions::GrayscaleAa);
+        let bounds = self
+            .font
+            .raster_bounds(
+                id,
+                self.pixel_size,
+                Transform2F::default(),
+                hinting,
+                rasterisation,
+            )
+            .ok()?;
+        let (width, height) = (bounds.width().max(0) as u32, bounds.height().max(0) as u32);
+        if width == 0 || height == 0 {
+            return Some(Glyph {
+                origin: Vector2::new(0, 0),
+                size: Vector2::new(0, 0),
+                bearing: Vector2::new(0.0, 0.0),
+                advance,
+            });
+        }
+
+        let mut canvas = Canvas::new(bounds.size(), canvas::Format::A8);
+        self.font
+            .rasterize_glyph(
+                &mut canvas,
+                id,
+                self.pixel_size,
+                Transform2F::from_translation(-bounds.origin().to_f32()),
+                hinting,
+                rasterisation,
+            )
+            .ok()?;
+
+        // fill the atlas shelf by shelf, starting a new one when a row is full
+        let (mut cursor, mut shelf_height) = self.shelf;
+        if cursor.x + width > ATLAS_SIZE {
+            cursor = Vector2::new(0, cursor.y + shelf_height + PADDING);
+            shelf_height = 0;
+        }
+        if cursor.y + height > ATLAS_SIZE {
+            return None;
+        }
+        self.shelf = (
+            Vector2::new(cursor.x + width + PADDING, cursor.y),
+            shelf_height.max(height),
+        );
+
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                255,
+                255,
+                255,
+                canvas.pixels[y as usize * canvas.stride + x as usize],
+            ])
+        });
+        self.atlas.replace_rect(
+            cursor.x as i32,
+            cursor.y as i32,
+            DynamicImage::ImageRgba8(image),
+        );
+
+        Some(Glyph {
+            origin: cursor,
+            size: Vector2::new(width, height),
+            // raster bounds are y-down from the baseline
+            bearing: Vector2::new(bounds.origin_x() as f32, -bounds.max_y() as f32),
+            advance,
+        })
+    }
+
+    pub fn update(&mut self) {
+        if self.needs_update && !self.rects.is_empty() {
+            let (freq, typ) = (fgl::AccessFrequency::Dynamic, fgl::AccessType::Draw);
+            self.vbos[1].alloc_with(&self.rects, freq, typ);
+            self.vbos[2].alloc_with(&self.uvs, freq, typ);
+            self.vbos[3].alloc_with(&self.colours, freq, typ);
+        }
+        self.needs_update = false;
+    }
+
+    pub fn draw(&self, projection: cgmath::Matrix4<f32>) {
+        if self.rects.is_empty() {
+            return;
+        }
+        self.vao.bind();
+        self.program.bind();
+        self.program.uniform_mat4("projection", &projection);
+        self.atlas.bind(0);
+        self.program.uniform_i32("atlas", 0);
+        unsafe {
+            gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6i32, self.rects.len() as i32);
+        }
+    }
+}
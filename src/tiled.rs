@@ -0,0 +1,975 @@
+use crate::hex::grid::{GridError, HexGrid, HexGridBuilder, BASE_LAYER};
+use crate::hex::layer::Orientation;
+use crate::hex::layout::Shape;
+use crate::hex::tile::Tile;
+use cgmath::Vector2;
+use image::{DynamicImage, GenericImageView};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_120: u32 = 0x1000_0000;
+const GID_MASK: u32 = 0x0fff_ffff;
+/// Put after the name of a Tiled layer called `BASE_LAYER`
+const TILED_SUFFIX: &str = " (Tiled)";
+
+/// A map made in the Tiled editor, converted to this grid's conventions
+///
+/// Tiled counts rows from the top, the grid from the bottom, and either may
+/// stagger odd or even rows (or columns). Where the two disagree the map gets
+/// an empty row or column on the bottom or left to line the stagger back up.
+pub struct TiledMap {
+    pub shape: Shape,
+    pub tile_size: u32,
+    pub dimensions: (u32, u32),
+    /// Every tileset's tiles one after the other, shared by all layers
+    pub tiles: Vec<Tile>,
+    /// Bottom to top, all going above the grid's own base layer, which is left empty
+    pub layers: Vec<TiledLayer>,
+}
+
+pub struct TiledLayer {
+    /// As in Tiled, except that `BASE_LAYER` is taken by the grid's own so
+    /// gets `TILED_SUFFIX` after it
+    pub name: String,
+    /// Index into `TiledMap::tiles` per hex, row by row
+    pub contents: Vec<Option<usize>>,
+    pub orientations: Vec<Orientation>,
+    pub visible: bool,
+    pub opacity: f32,
+}
+
+/// Whether `path` looks like a Tiled map, going by its extension
+pub fn is_tiled(path: impl AsRef<Path>) -> bool {
+    let extension = path.as_ref().extension().and_then(|e| e.to_str());
+    matches!(extension, Some("tmx") | Some("tmj"))
+}
+
+impl TiledMap {
+    /// Reads a `.tmx` (XML) or `.tmj` (JSON) map and every tileset and image it uses
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TiledError> {
+        let path = path.as_ref();
+        let text = read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let map = match path.extension().and_then(|e| e.to_str()) {
+            Some("tmj") | Some("json") => parse_json_map(&text, dir)?,
+            _ => parse_xml_map(&text, dir)?,
+        };
+        map.convert()
+    }
+
+    pub fn build(&self) -> Result<HexGrid, TiledError> {
+        let (width, height) = self.dimensions;
+        let empty = vec![None; (width * height) as usize];
+        let used: Vec<_> = self
+            .layers
+            .iter()
+            .map(|layer| self.used_tiles(layer))
+            .collect();
+        let mut builder = HexGridBuilder::default()
+            .with_dimensions(width, height)
+            .with_tile_size(self.tile_size)
+            .with_grid_contents(&empty);
+        builder = match self.shape {
+            Shape::Hex { point_up: true } => builder.point_up(),
+            Shape::Square => builder.square(),
+            _ => builder,
+        };
+        let mut hex_grid = builder.build();
+
+        for (layer, (tiles, contents)) in self.layers.iter().zip(&used) {
+            hex_grid
+                .add_layer(&layer.name, tiles)
+                .map_err(TiledError::Grid)?;
+            let cells = contents.iter().zip(&layer.orientations);
+            let tiles = cells.enumerate().filter_map(|(idx, (tile, orientation))| {
+                let coords = Vector2::new(idx as u32 % width, idx as u32 / width);
+                tile.map(|tile| (coords, Some(tile), *orientation))
+            });
+            hex_grid
+                .set_tiles(&layer.name, tiles)
+                .map_err(TiledError::Grid)?;
+            let tile_layer = hex_grid.layer_mut(&layer.name).unwrap();
+            tile_layer.visible = layer.visible;
+            tile_layer.opacity = layer.opacity;
+        }
+        Ok(hex_grid)
+    }
+
+    /// Just the tiles `layer` uses, so each layer's atlas only holds those,
+    /// and its contents as indices into them
+    fn used_tiles(&self, layer: &TiledLayer) -> (Vec<Tile>, Vec<Option<usize>>) {
+        let mut tiles = Vec::new();
+        let mut indices = HashMap::new();
+        let contents = layer
+            .contents
+            .iter()
+            .map(|tile| {
+                tile.map(|tile| {
+                    *indices.entry(tile).or_insert_with(|| {
+                        tiles.push(self.tiles[tile].clone());
+                        tiles.len() - 1
+                    })
+                })
+            })
+            .collect();
+        (tiles, contents)
+    }
+}
+
+/// The parts of a map both file formats have in common, still in Tiled's terms
+struct RawMap {
+    orientation: String,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    hex_side_length: u32,
+    stagger_axis: String,
+    stagger_index: String,
+    infinite: bool,
+    /// First global id and the tileset it starts
+    tilesets: Vec<(u32, RawTileset)>,
+    layers: Vec<RawLayer>,
+}
+
+/// Either one image cut into a sheet of tiles or a collection of separate images
+#[derive(Default)]
+struct RawTileset {
+    name: String,
+    /// What image paths are relative to
+    dir: PathBuf,
+    image: Option<String>,
+    tile_width: u32,
+    tile_height: u32,
+    spacing: u32,
+    margin: u32,
+    columns: u32,
+    tile_count: u32,
+    /// Local id and image path of each tile in a collection
+    tiles: Vec<(u32, String)>,
+    /// Local id of each animated tile, with the local id and milliseconds of its frames
+    animations: Vec<(u32, Vec<(u32, u32)>)>,
+}
+
+struct RawLayer {
+    name: String,
+    visible: bool,
+    opacity: f32,
+    /// Global ids with the flip flags still in them, rows from the top
+    gids: Vec<u32>,
+}
+
+impl RawMap {
+    fn convert(mut self) -> Result<TiledMap, TiledError> {
+        if self.infinite {
+            return Err(TiledError::Unsupported("infinite maps".to_string()));
+        }
+        let (shape, tile_size, shift) = match self.orientation.as_str() {
+            "hexagonal" => self.hex_layout()?,
+            "orthogonal" if self.tile_width == self.tile_height => {
+                (Shape::Square, self.tile_width, (0, 0))
+            }
+            "orthogonal" => {
+                return Err(TiledError::Unsupported(format!(
+                    "{}x{} tiles, only square ones",
+                    self.tile_width, self.tile_height
+                )))
+            }
+            other => return Err(TiledError::Unsupported(format!("{} maps", other))),
+        };
+        let hex = shape != Shape::Square;
+        let dimensions = (self.width + shift.0, self.height + shift.1);
+
+        let mut tiles = Vec::new();
+        // first global id, index of its first tile and where each local id went
+        let mut ranges = Vec::new();
+        let mut tilesets = std::mem::take(&mut self.tilesets);
+        tilesets.sort_by_key(|(first_gid, _)| *first_gid);
+        for (first_gid, tileset) in tilesets {
+            let start = tiles.len();
+            let ids = tileset.load(&mut tiles)?;
+            ranges.push((first_gid, start, ids));
+        }
+        let lookup = |gid: u32| {
+            let (first_gid, start, ids) =
+                ranges.iter().rev().find(|(first, _, _)| *first <= gid)?;
+            ids.get(&(gid - first_gid)).map(|idx| start + idx)
+        };
+
+        let mut layers: Vec<TiledLayer> = Vec::new();
+        for layer in std::mem::take(&mut self.layers) {
+            let name = if layer.name == BASE_LAYER {
+                layer.name.clone() + TILED_SUFFIX
+            } else {
+                layer.name.clone()
+            };
+            if layers.iter().any(|l| l.name == name) {
+                return Err(TiledError::Unsupported(format!(
+                    "two layers called {}",
+                    name
+                )));
+            }
+            if layer.gids.len() != (self.width * self.height) as usize {
+                return Err(TiledError::Invalid(format!(
+                    "layer {} has {} tiles, the map {}",
+                    layer.name,
+                    layer.gids.len(),
+                    self.width * self.height
+                )));
+            }
+            let size = (dimensions.0 * dimensions.1) as usize;
+            let mut contents = vec![None; size];
+            let mut orientations = vec![Orientation::default(); size];
+            for (idx, gid) in layer.gids.iter().enumerate() {
+                if gid & GID_MASK == 0 {
+                    continue;
+                }
+                let tile = lookup(gid & GID_MASK).ok_or_else(|| {
+                    TiledError::Invalid(format!(
+                        "layer {} uses unknown tile {}",
+                        layer.name,
+                        gid & GID_MASK
+                    ))
+                })?;
+                let coords = self.grid_coords(idx, shift);
+                let cell = (coords.0 + dimensions.0 * coords.1) as usize;
+                contents[cell] = Some(tile);
+                orientations[cell] = orientation(*gid, hex);
+            }
+            layers.push(TiledLayer {
+                name,
+                contents,
+                orientations,
+                visible: layer.visible,
+                opacity: layer.opacity,
+            });
+        }
+
+        Ok(TiledMap {
+            shape,
+            tile_size,
+            dimensions,
+            tiles,
+            layers,
+        })
+    }
+
+    /// Where the tile at `idx` of a layer goes on the grid, `shift` coming from `hex_layout`
+    fn grid_coords(&self, idx: usize, shift: (u32, u32)) -> (u32, u32) {
+        let (column, row) = (idx as u32 % self.width, idx as u32 / self.width);
+        (column + shift.0, self.height - 1 - row + shift.1)
+    }
+
+    /// Shape, tile size and how far to move every tile to get the stagger right
+    fn hex_layout(&self) -> Result<(Shape, u32, (u32, u32)), TiledError> {
+        let odd = match self.stagger_index.as_str() {
+            "odd" => true,
+            "even" => false,
+            other => return Err(TiledError::Invalid(format!("stagger index {}", other))),
+        };
+        // Tiled measures hexes by their bounding box, which for regular ones
+        // is as long as the tile size and as wide as twice the short radius
+        let (point_up, long, short) = match self.stagger_axis.as_str() {
+            "y" => (true, self.tile_height, self.tile_width),
+            "x" => (false, self.tile_width, self.tile_height),
+            other => return Err(TiledError::Invalid(format!("stagger axis {}", other))),
+        };
+        let long_f = long as f32;
+        let regular = (short as f32 - long_f * 3f32.sqrt() / 2.0).abs() <= long_f * 0.1
+            && (self.hex_side_length as f32 - long_f / 2.0).abs() <= long_f * 0.1;
+        if !regular {
+            return Err(TiledError::Unsupported(format!(
+                "irregular {}x{} hexes with {} long sides",
+                self.tile_width, self.tile_height, self.hex_side_length
+            )));
+        }
+
+        // the grid shifts its odd rows (counted from the bottom) right and its
+        // even columns down, Tiled whichever the stagger index says
+        let shift = if point_up {
+            (0, (self.height + odd as u32) % 2)
+        } else {
+            (odd as u32, 0)
+        };
+        Ok((Shape::Hex { point_up }, long, shift))
+    }
+}
+
+impl RawTileset {
+    /// Appends the tiles to `tiles`, returning where each local id ended up relative to the first
+    fn load(self, tiles: &mut Vec<Tile>) -> Result<HashMap<u32, usize>, TiledError> {
+        let start = tiles.len();
+        let mut ids = HashMap::new();
+        match &self.image {
+            Some(image) => {
+                let sheet = open(&self.dir.join(image))?;
+                if self.tile_width == 0 || self.tile_height == 0 {
+                    return Err(TiledError::Invalid(format!(
+                        "tileset {} has no tile size",
+                        self.name
+                    )));
+                }
+                let step = (
+                    self.tile_width + self.spacing,
+                    self.tile_height + self.spacing,
+                );
+                // how many tiles fit along an edge of the sheet
+                let fit = |extent: u32, step: u32| {
+                    (extent + self.spacing)
+                        .checked_sub(self.margin)
+                        .map_or(0, |room| room / step)
+                };
+                let columns = match self.columns {
+                    0 => fit(sheet.width(), step.0),
+                    columns => columns,
+                };
+                let rows = fit(sheet.height(), step.1);
+                if columns == 0 || rows == 0 {
+                    return Err(TiledError::Invalid(format!(
+                        "tileset {}'s image doesn't fit a single tile",
+                        self.name
+                    )));
+                }
+                let count = match self.tile_count {
+                    0 => columns * rows,
+                    count => count,
+                };
+                for id in 0..count {
+                    let x = self.margin + id % columns * step.0;
+                    let y = self.margin + id / columns * step.1;
+                    if x + self.tile_width > sheet.width() || y + self.tile_height > sheet.height()
+                    {
+                        return Err(TiledError::Invalid(format!(
+                            "tile {} lies outside tileset {}'s image",
+                            id, self.name
+                        )));
+                    }
+                    ids.insert(id, ids.len());
+                    let image = sheet.crop_imm(x, y, self.tile_width, self.tile_height);
+                    tiles.push(Tile::Still(image));
+                }
+            }
+            None => {
+                for (id, image) in &self.tiles {
+                    ids.insert(*id, ids.len());
+                    tiles.push(Tile::Still(open(&self.dir.join(image))?));
+                }
+            }
+        }
+
+        let index = |id: &u32| {
+            ids.get(id).map(|idx| start + idx).ok_or_else(|| {
+                TiledError::Invalid(format!("tileset {} has no tile {}", self.name, id))
+            })
+        };
+        let mut animated = Vec::new();
+        for (id, frames) in &self.animations {
+            let mut images = Vec::new();
+            for (frame, duration) in frames {
+                let image = tiles[index(frame)?].first_frame().clone();
+                images.push((image, Duration::from_millis(u64::from(*duration))));
+            }
+            let tile = Tile::animated(images).ok_or_else(|| {
+                TiledError::Invalid(format!(
+                    "tileset {} has an empty animation on tile {}",
+                    self.name, id
+                ))
+            })?;
+            animated.push((index(id)?, tile));
+        }
+        for (idx, tile) in animated {
+            tiles[idx] = tile;
+        }
+        Ok(ids)
+    }
+}
+
+/// Turns Tiled's flip flags into the rotation and mirroring they amount to
+///
+/// Tiled flips hex tiles first and then rotates them, using the diagonal flag
+/// for a 60° turn. On square maps the diagonal flip comes first instead.
+fn orientation(gid: u32, hex: bool) -> Orientation {
+    let turn = if hex { 6 } else { 4 };
+    // rotation after an optional left to right mirror, like Orientation
+    let compose = |(a, mirror_a): (u8, bool), (b, mirror_b): (u8, bool)| {
+        let b = if mirror_a { turn - b } else { b };
+        ((a + b) % turn, mirror_a != mirror_b)
+    };
+    let flag = |flag: u32, transform: (u8, bool)| {
+        if gid & flag != 0 {
+            transform
+        } else {
+            (0, false)
+        }
+    };
+    let horizontal = flag(FLIPPED_HORIZONTALLY, (0, true));
+    let vertical = flag(FLIPPED_VERTICALLY, (turn / 2, true));
+    let (rotation, mirrored) = if hex {
+        let rotation = flag(FLIPPED_DIAGONALLY, (1, false));
+        let rotation = compose(rotation, flag(ROTATED_120, (2, false)));
+        compose(rotation, compose(horizontal, vertical))
+    } else {
+        let diagonal = flag(FLIPPED_DIAGONALLY, (3, true));
+        compose(vertical, compose(horizontal, diagonal))
+    };
+    Orientation { rotation, mirrored }
+}
+
+fn read_to_string(path: &Path) -> Result<String, TiledError> {
+    std::fs::read_to_string(path).map_err(|e| TiledError::Io(path.to_path_buf(), e))
+}
+
+fn open(path: &Path) -> Result<DynamicImage, TiledError> {
+    image::open(path).map_err(|e| TiledError::Image(path.to_path_buf(), e))
+}
+
+/// Layer data as written to the file: csv or base64, the latter maybe compressed
+fn decode(text: &str, encoding: &str, compression: &str) -> Result<Vec<u32>, TiledError> {
+    match encoding {
+        "csv" => text
+            .split(',')
+            .map(|gid| {
+                gid.trim()
+                    .parse()
+                    .map_err(|_| TiledError::Invalid(format!("tile {} in csv data", gid.trim())))
+            })
+            .collect(),
+        "base64" => {
+            use base64::Engine;
+            let text: String = text.split_whitespace().collect();
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(text)
+                .map_err(|e| TiledError::Invalid(format!("base64 data: {}", e)))?;
+            let bytes = match compression {
+                "" => bytes,
+                "zlib" => inflate(flate2::read::ZlibDecoder::new(&bytes[..]))?,
+                "gzip" => inflate(flate2::read::GzDecoder::new(&bytes[..]))?,
+                other => {
+                    return Err(TiledError::Unsupported(format!(
+                        "{} compressed layers",
+                        other
+                    )))
+                }
+            };
+            if bytes.len() % 4 != 0 {
+                return Err(TiledError::Invalid("truncated layer data".to_string()));
+            }
+            Ok(bytes
+                .chunks(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect())
+        }
+        other => Err(TiledError::Unsupported(format!("{} encoded layers", other))),
+    }
+}
+
+fn inflate(mut decoder: impl Read) -> Result<Vec<u8>, TiledError> {
+    let mut bytes = Vec::new();
+    decoder
+        .read_to_end(&mut bytes)
+        .map_err(|e| TiledError::Invalid(format!("compressed layer data: {}", e)))?;
+    Ok(bytes)
+}
+
+fn attribute<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> Result<Option<T>, TiledError> {
+    node.attribute(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                TiledError::Invalid(format!(
+                    "{}=\"{}\" on <{}>",
+                    name,
+                    value,
+                    node.tag_name().name()
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn required<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, TiledError> {
+    attribute(node, name)?
+        .ok_or_else(|| TiledError::Invalid(format!("<{}> has no {}", node.tag_name().name(), name)))
+}
+
+fn parse_xml_map(text: &str, dir: &Path) -> Result<RawMap, TiledError> {
+    let document = roxmltree::Document::parse(text).map_err(|e| TiledError::Xml(e.to_string()))?;
+    let map = document.root_element();
+    if map.tag_name().name() != "map" {
+        return Err(TiledError::Invalid("not a Tiled map".to_string()));
+    }
+
+    let mut tilesets = Vec::new();
+    let mut layers = Vec::new();
+    for child in map.children().filter(roxmltree::Node::is_element) {
+        match child.tag_name().name() {
+            "tileset" => {
+                let tileset = match child.attribute("source") {
+                    Some(source) => load_tileset(&dir.join(source))?,
+                    None => parse_xml_tileset(child, dir)?,
+                };
+                tilesets.push((required(child, "firstgid")?, tileset));
+            }
+            "layer" => {
+                let name: String = attribute(child, "name")?.unwrap_or_default();
+                let data = child
+                    .children()
+                    .find(|node| node.has_tag_name("data"))
+                    .ok_or_else(|| TiledError::Invalid(format!("layer {} has no data", name)))?;
+                if data.children().any(|node| node.has_tag_name("chunk")) {
+                    return Err(TiledError::Unsupported("infinite maps".to_string()));
+                }
+                let gids = match data.attribute("encoding") {
+                    Some(encoding) => decode(
+                        data.text().unwrap_or(""),
+                        encoding,
+                        data.attribute("compression").unwrap_or(""),
+                    )?,
+                    None => data
+                        .children()
+                        .filter(|node| node.has_tag_name("tile"))
+                        .map(|tile| Ok(attribute(tile, "gid")?.unwrap_or(0)))
+                        .collect::<Result<_, TiledError>>()?,
+                };
+                layers.push(RawLayer {
+                    visible: attribute::<u8>(child, "visible")?.unwrap_or(1) != 0,
+                    opacity: attribute(child, "opacity")?.unwrap_or(1.0),
+                    name,
+                    gids,
+                });
+            }
+            "objectgroup" => return Err(TiledError::Unsupported("object layers".to_string())),
+            "imagelayer" => return Err(TiledError::Unsupported("image layers".to_string())),
+            "group" => return Err(TiledError::Unsupported("group layers".to_string())),
+            _ => {}
+        }
+    }
+
+    Ok(RawMap {
+        orientation: required(map, "orientation")?,
+        width: required(map, "width")?,
+        height: required(map, "height")?,
+        tile_width: required(map, "tilewidth")?,
+        tile_height: required(map, "tileheight")?,
+        hex_side_length: attribute(map, "hexsidelength")?.unwrap_or(0),
+        stagger_axis: attribute(map, "staggeraxis")?.unwrap_or_default(),
+        stagger_index: attribute(map, "staggerindex")?.unwrap_or_default(),
+        infinite: attribute::<u8>(map, "infinite")?.unwrap_or(0) != 0,
+        tilesets,
+        layers,
+    })
+}
+
+fn parse_xml_tileset(tileset: roxmltree::Node, dir: &Path) -> Result<RawTileset, TiledError> {
+    let image = |node: roxmltree::Node| {
+        node.children()
+            .find(|child| child.has_tag_name("image"))
+            .map(|image| required::<String>(image, "source"))
+            .transpose()
+    };
+    let mut tiles = Vec::new();
+    let mut animations = Vec::new();
+    for tile in tileset.children().filter(|node| node.has_tag_name("tile")) {
+        let id = required(tile, "id")?;
+        if let Some(source) = image(tile)? {
+            tiles.push((id, source));
+        }
+        if let Some(animation) = tile.children().find(|node| node.has_tag_name("animation")) {
+            let frames = animation
+                .children()
+                .filter(|node| node.has_tag_name("frame"))
+                .map(|frame| Ok((required(frame, "tileid")?, required(frame, "duration")?)))
+                .collect::<Result<_, TiledError>>()?;
+            animations.push((id, frames));
+        }
+    }
+    Ok(RawTileset {
+        name: attribute(tileset, "name")?.unwrap_or_default(),
+        dir: dir.to_path_buf(),
+        image: image(tileset)?,
+        tile_width: attribute(tileset, "tilewidth")?.unwrap_or(0),
+        tile_height: attribute(tileset, "tileheight")?.unwrap_or(0),
+        spacing: attribute(tileset, "spacing")?.unwrap_or(0),
+        margin: attribute(tileset, "margin")?.unwrap_or(0),
+        columns: attribute(tileset, "columns")?.unwrap_or(0),
+        tile_count: attribute(tileset, "tilecount")?.unwrap_or(0),
+        tiles,
+        animations,
+    })
+}
+
+/// An external `.tsx` or `.tsj` tileset
+fn load_tileset(path: &Path) -> Result<RawTileset, TiledError> {
+    let text = read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tsj") | Some("json") => {
+            let tileset: JsonTileset =
+                serde_json::from_str(&text).map_err(|e| TiledError::Json(e.to_string()))?;
+            Ok(tileset.into_raw(dir))
+        }
+        _ => {
+            let document =
+                roxmltree::Document::parse(&text).map_err(|e| TiledError::Xml(e.to_string()))?;
+            parse_xml_tileset(document.root_element(), dir)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    orientation: String,
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    hexsidelength: u32,
+    #[serde(default)]
+    staggeraxis: String,
+    #[serde(default)]
+    staggerindex: String,
+    #[serde(default)]
+    infinite: bool,
+    tilesets: Vec<JsonTilesetRef>,
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonTilesetRef {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(flatten)]
+    tileset: JsonTileset,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonTileset {
+    name: String,
+    image: Option<String>,
+    tilewidth: u32,
+    tileheight: u32,
+    spacing: u32,
+    margin: u32,
+    columns: u32,
+    tilecount: u32,
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    image: Option<String>,
+    #[serde(default)]
+    animation: Vec<JsonFrame>,
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    tileid: u32,
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    data: Option<serde_json::Value>,
+    #[serde(default = "default_encoding")]
+    encoding: String,
+    #[serde(default)]
+    compression: String,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_encoding() -> String {
+    "csv".to_string()
+}
+
+impl JsonTileset {
+    fn into_raw(self, dir: &Path) -> RawTileset {
+        RawTileset {
+            name: self.name,
+            dir: dir.to_path_buf(),
+            image: self.image,
+            tile_width: self.tilewidth,
+            tile_height: self.tileheight,
+            spacing: self.spacing,
+            margin: self.margin,
+            columns: self.columns,
+            tile_count: self.tilecount,
+            tiles: self
+                .tiles
+                .iter()
+                .filter_map(|tile| Some((tile.id, tile.image.clone()?)))
+                .collect(),
+            animations: self
+                .tiles
+                .into_iter()
+                .filter(|tile| !tile.animation.is_empty())
+                .map(|tile| {
+                    let frames = tile.animation.iter();
+                    (tile.id, frames.map(|f| (f.tileid, f.duration)).collect())
+                })
+                .collect(),
+        }
+    }
+}
+
+fn parse_json_map(text: &str, dir: &Path) -> Result<RawMap, TiledError> {
+    let map: JsonMap = serde_json::from_str(text).map_err(|e| TiledError::Json(e.to_string()))?;
+
+    let tilesets = map
+        .tilesets
+        .into_iter()
+        .map(|tileset| {
+            let raw = match &tileset.source {
+                Some(source) => load_tileset(&dir.join(source))?,
+                None => tileset.tileset.into_raw(dir),
+            };
+            Ok((tileset.firstgid, raw))
+        })
+        .collect::<Result<_, TiledError>>()?;
+
+    let mut layers = Vec::new();
+    for mut layer in map.layers {
+        let gids = match (layer.kind.as_str(), layer.data.take()) {
+            ("tilelayer", Some(serde_json::Value::String(text))) => {
+                decode(&text, &layer.encoding, &layer.compression)?
+            }
+            ("tilelayer", Some(data)) => serde_json::from_value(data)
+                .map_err(|e| TiledError::Json(format!("layer {}: {}", layer.name, e)))?,
+            ("tilelayer", None) => {
+                return Err(TiledError::Unsupported("infinite maps".to_string()))
+            }
+            ("objectgroup", _) => return Err(TiledError::Unsupported("object layers".to_string())),
+            ("imagelayer", _) => return Err(TiledError::Unsupported("image layers".to_string())),
+            ("group", _) => return Err(TiledError::Unsupported("group layers".to_string())),
+            (other, _) => return Err(TiledError::Unsupported(format!("{} layers", other))),
+        };
+        layers.push(RawLayer {
+            name: layer.name,
+            visible: layer.visible,
+            opacity: layer.opacity,
+            gids,
+        });
+    }
+
+    Ok(RawMap {
+        orientation: map.orientation,
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        hex_side_length: map.hexsidelength,
+        stagger_axis: map.staggeraxis,
+        stagger_index: map.staggerindex,
+        infinite: map.infinite,
+        tilesets,
+        layers,
+    })
+}
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(PathBuf, std::io::Error),
+    Xml(String),
+    Json(String),
+    Image(PathBuf, image::ImageError),
+    /// Something Tiled can do that the grid can't
+    Unsupported(String),
+    /// Something Tiled wouldn't have written
+    Invalid(String),
+    Grid(GridError),
+}
+
+impl std::fmt::Display for TiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TiledError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+            TiledError::Xml(e) => write!(f, "couldn't parse the map: {}", e),
+            TiledError::Json(e) => write!(f, "couldn't parse the map: {}", e),
+            TiledError::Image(path, e) => write!(f, "couldn't load {}: {}", path.display(), e),
+            TiledError::Unsupported(what) => write!(f, "can't import {}", what),
+            TiledError::Invalid(what) => write!(f, "invalid map: {}", what),
+            TiledError::Grid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hex map of regular 32 pixel tiles
+    fn hex_map(width: u32, height: u32, axis: &str, index: &str) -> RawMap {
+        let (tile_width, tile_height) = match axis {
+            "y" => (28, 32),
+            _ => (32, 28),
+        };
+        RawMap {
+            orientation: "hexagonal".to_string(),
+            width,
+            height,
+            tile_width,
+            tile_height,
+            hex_side_length: 16,
+            stagger_axis: axis.to_string(),
+            stagger_index: index.to_string(),
+            infinite: false,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn staggered_rows_land_on_shifted_rows() {
+        for &height in &[4, 5] {
+            for &(index, odd) in &[("odd", true), ("even", false)] {
+                let map = hex_map(3, height, "y", index);
+                let (shape, _, shift) = map.hex_layout().unwrap();
+                assert_eq!(shape, Shape::Hex { point_up: true });
+                for row in 0..height {
+                    let (_, y) = map.grid_coords((row * map.width) as usize, shift);
+                    // the grid shifts its odd rows right
+                    assert_eq!(
+                        y % 2 == 1,
+                        (row % 2 == 1) == odd,
+                        "row {} of {}",
+                        row,
+                        height
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn staggered_columns_land_on_shifted_columns() {
+        for &height in &[4, 5] {
+            for &(index, odd) in &[("odd", true), ("even", false)] {
+                let map = hex_map(4, height, "x", index);
+                let (shape, _, shift) = map.hex_layout().unwrap();
+                assert_eq!(shape, Shape::Hex { point_up: false });
+                for column in 0..map.width {
+                    let (x, _) = map.grid_coords(column as usize, shift);
+                    // the grid shifts its even columns down
+                    assert_eq!(x % 2 == 0, (column % 2 == 1) == odd, "column {}", column);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rows_are_flipped() {
+        let map = hex_map(3, 4, "y", "even");
+        let (_, _, shift) = map.hex_layout().unwrap();
+        assert_eq!(map.grid_coords(0, shift), (0, 3 + shift.1));
+        assert_eq!(map.grid_coords(11, shift), (2, shift.1));
+    }
+
+    #[test]
+    fn layers_keep_their_order_and_leave_the_base_to_the_grid() {
+        let mut map = hex_map(2, 2, "y", "odd");
+        for name in &["top", BASE_LAYER, "roofs"] {
+            map.layers.push(RawLayer {
+                name: name.to_string(),
+                visible: true,
+                opacity: 1.0,
+                gids: vec![0; 4],
+            });
+        }
+        let names: Vec<_> = map
+            .convert()
+            .unwrap()
+            .layers
+            .into_iter()
+            .map(|layer| layer.name)
+            .collect();
+        assert_eq!(names, vec!["top", "base (Tiled)", "roofs"]);
+    }
+
+    #[test]
+    fn layers_only_get_the_tiles_they_use() {
+        let tile = |width| Tile::Still(DynamicImage::new_rgba8(width, 1));
+        let map = TiledMap {
+            shape: Shape::Square,
+            tile_size: 1,
+            dimensions: (4, 1),
+            tiles: (1..=4).map(tile).collect(),
+            layers: Vec::new(),
+        };
+        let layer = TiledLayer {
+            name: "top".to_string(),
+            contents: vec![Some(2), None, Some(0), Some(2)],
+            orientations: vec![Orientation::default(); 4],
+            visible: true,
+            opacity: 1.0,
+        };
+        let (tiles, contents) = map.used_tiles(&layer);
+        let widths: Vec<_> = tiles
+            .iter()
+            .map(|tile| tile.first_frame().width())
+            .collect();
+        assert_eq!(widths, vec![3, 1]);
+        assert_eq!(contents, vec![Some(0), None, Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn irregular_hexes_are_refused() {
+        let mut map = hex_map(3, 3, "y", "odd");
+        map.tile_width = 32;
+        assert!(map.hex_layout().is_err());
+        let mut map = hex_map(3, 3, "y", "odd");
+        map.hex_side_length = 0;
+        assert!(map.hex_layout().is_err());
+        assert!(hex_map(3, 3, "z", "odd").hex_layout().is_err());
+        assert!(hex_map(3, 3, "y", "both").hex_layout().is_err());
+    }
+
+    #[test]
+    fn spritesheets_too_small_for_a_tile_are_refused() {
+        let dir = std::env::temp_dir();
+        let name = format!("vtt-tiled-test-{}.png", std::process::id());
+        image::RgbaImage::new(16, 16).save(dir.join(&name)).unwrap();
+        let tileset = |margin, tile_size| RawTileset {
+            name: "sheet".to_string(),
+            dir: dir.clone(),
+            image: Some(name.clone()),
+            tile_width: tile_size,
+            tile_height: tile_size,
+            margin,
+            ..RawTileset::default()
+        };
+
+        let mut tiles = Vec::new();
+        assert!(tileset(32, 8).load(&mut tiles).is_err());
+        assert!(tileset(0, 32).load(&mut tiles).is_err());
+        assert_eq!(tileset(0, 8).load(&mut tiles).unwrap().len(), 4);
+        assert_eq!(tiles.len(), 4);
+        std::fs::remove_file(dir.join(&name)).unwrap();
+    }
+}